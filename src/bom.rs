@@ -0,0 +1,201 @@
+use crate::mappings::{ArtifactMapping, ARTIFACT_MAPPINGS};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The versions needed to cover a set of AndroidX classes. AndroidX has no single BOM, so a version
+/// is suggested for each artifact group instead, taken from the artifact mappings.
+#[derive(Debug, Default, PartialEq)]
+pub struct BomSuggestion {
+    /// The version of each artifact group that includes any of the classes
+    pub versions: BTreeMap<String, String>,
+    /// AndroidX classes that aren't part of any known artifact group
+    pub unknown: BTreeSet<String>,
+}
+
+/// Find the version of every AndroidX artifact group needed to include the given classes, from
+/// the versions the artifact mappings migrate to.
+///
+/// * `classes` - The classes introduced by the migration
+///
+/// Returns the suggested versions along with any AndroidX classes they aren't known to cover
+pub fn suggest_bom_version<'a>(classes: impl IntoIterator<Item = &'a String>) -> BomSuggestion {
+    suggest_versions(&ARTIFACT_MAPPINGS, classes)
+}
+
+/// Find the version of every artifact group needed to include the given classes. A class is part
+/// of the group with the longest id that is one of its packages, e.g. `androidx.arch.core.util`
+/// is part of `androidx.arch.core` rather than `androidx.arch`.
+///
+/// * `artifacts` - The artifact mappings along with the versions they migrate to
+/// * `classes` - The classes introduced by the migration
+///
+/// Returns the suggested versions along with any AndroidX classes they aren't known to cover
+fn suggest_versions<'a>(
+    artifacts: &[ArtifactMapping],
+    classes: impl IntoIterator<Item = &'a String>,
+) -> BomSuggestion {
+    // The newest version of any artifact in each group
+    let mut groups: BTreeMap<&str, &str> = BTreeMap::new();
+    for artifact in artifacts {
+        let group = artifact.replacement.split(':').next().unwrap_or_default();
+        if let Some(version) = artifact.version.as_deref() {
+            let newest = groups.entry(group).or_insert(version);
+            if version_key(version) > version_key(newest) {
+                *newest = version;
+            }
+        }
+    }
+
+    let mut suggestion = BomSuggestion::default();
+    for class in classes {
+        let group = groups
+            .iter()
+            .filter(|(group, _)| in_package(class, group))
+            .max_by_key(|(group, _)| group.len());
+        match group {
+            Some((group, version)) => {
+                suggestion
+                    .versions
+                    .insert(group.to_string(), version.to_string());
+            }
+            None if class.starts_with("androidx.") => {
+                suggestion.unknown.insert(class.clone());
+            }
+            None => {}
+        }
+    }
+    suggestion
+}
+
+/// A key to order versions by, e.g. `1.0.0-alpha04` < `1.0.0` < `1.1.0`. Pre-releases come before
+/// the release they lead up to.
+///
+/// * `version` - The version to order
+fn version_key(version: &str) -> (Vec<u64>, bool) {
+    let (release, pre_release) = match version.split_once('-') {
+        Some((release, _)) => (release, true),
+        None => (version, false),
+    };
+    let numbers = release
+        .split('.')
+        .map(|number| number.parse().unwrap_or_default())
+        .collect();
+    (numbers, !pre_release)
+}
+
+/// Whether a class is part of a package or one of its subpackages.
+///
+/// * `class` - The fully qualified class name
+/// * `package` - The package name
+fn in_package(class: &str, package: &str) -> bool {
+    class
+        .strip_prefix(package)
+        .is_some_and(|rest| rest.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn artifact(replacement: &str, version: &str) -> ArtifactMapping {
+        ArtifactMapping {
+            pattern: Regex::new("unused").unwrap(),
+            replacement: String::from(replacement),
+            version: Some(String::from(version)),
+        }
+    }
+
+    fn artifacts() -> Vec<ArtifactMapping> {
+        vec![
+            artifact("androidx.arch.core:core-common", "2.1.0"),
+            artifact("androidx.core:core", "1.1.0"),
+            artifact("androidx.media2:media2", "1.0.0-alpha04"),
+            artifact("androidx.media2:media2-exoplayer", "1.0.0"),
+            artifact("com.google.android.material:material", "1.0.0"),
+        ]
+    }
+
+    fn classes(classes: &[&str]) -> Vec<String> {
+        classes.iter().map(|c| String::from(*c)).collect()
+    }
+
+    #[test]
+    fn every_built_in_replacement_is_covered() {
+        let replacements: Vec<String> = crate::mappings::SUPPORT_MAPPINGS
+            .iter()
+            .chain(crate::mappings::ARCH_MAPPINGS.iter())
+            .map(|mapping| mapping.replacement.clone())
+            .filter(|replacement| !replacement.starts_with("androidx.databinding."))
+            .collect();
+
+        assert_eq!(suggest_bom_version(&replacements).unknown, BTreeSet::new());
+    }
+
+    #[test]
+    fn version_of_each_group_is_suggested() {
+        let suggestion = suggest_versions(
+            &artifacts(),
+            &classes(&[
+                "androidx.core.app.ActivityCompat",
+                "androidx.arch.core.util.Function",
+                "com.google.android.material.snackbar.Snackbar",
+            ]),
+        );
+
+        assert_eq!(
+            suggestion.versions,
+            BTreeMap::from([
+                (String::from("androidx.arch.core"), String::from("2.1.0")),
+                (String::from("androidx.core"), String::from("1.1.0")),
+                (
+                    String::from("com.google.android.material"),
+                    String::from("1.0.0")
+                ),
+            ])
+        );
+        assert!(suggestion.unknown.is_empty());
+    }
+
+    #[test]
+    fn newest_version_in_a_group_is_suggested() {
+        let suggestion = suggest_versions(&artifacts(), &classes(&["androidx.media2.MediaPlayer"]));
+
+        assert_eq!(
+            suggestion
+                .versions
+                .get("androidx.media2")
+                .map(String::as_str),
+            Some("1.0.0")
+        );
+    }
+
+    #[test]
+    fn unknown_classes_are_reported() {
+        let suggestion = suggest_versions(
+            &artifacts(),
+            &classes(&["androidx.core.app.ActivityCompat", "androidx.work.Worker"]),
+        );
+
+        assert_eq!(
+            suggestion.unknown,
+            BTreeSet::from([String::from("androidx.work.Worker")])
+        );
+    }
+
+    #[test]
+    fn pre_releases_come_before_releases() {
+        assert!(version_key("1.0.0-alpha04") < version_key("1.0.0"));
+        assert!(version_key("1.0.0") < version_key("1.1.0"));
+        assert!(version_key("2.0.0") > version_key("1.10.0"));
+    }
+
+    #[test]
+    fn package_prefix_must_end_on_boundary() {
+        assert!(in_package(
+            "androidx.core.app.ActivityCompat",
+            "androidx.core"
+        ));
+        assert!(!in_package("androidx.coreui.Thing", "androidx.core"));
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Everything that was found in a single file, for the changelog
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChangelogEntry {
+    /// The path of the file
+    pub path: PathBuf,
+    /// How many lines were migrated
+    pub replacements: usize,
+    /// Old artifacts that must be updated, as `<old> => <new>`
+    pub artifacts: Vec<String>,
+    /// Star imports that must be migrated by hand
+    pub star_imports: Vec<String>,
+}
+
+impl ChangelogEntry {
+    /// Whether anything in the file is worth mentioning.
+    pub fn is_empty(&self) -> bool {
+        self.replacements == 0 && self.artifacts.is_empty() && self.star_imports.is_empty()
+    }
+}
+
+/// Format the changelog as Markdown, with a bullet per file sorted by path.
+///
+/// * `entries` - What was found in each file
+///
+/// Returns the full contents of the changelog
+pub fn format_changelog(entries: &[ChangelogEntry]) -> String {
+    let mut entries: Vec<&ChangelogEntry> = entries.iter().filter(|e| !e.is_empty()).collect();
+    entries.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+    let replacements: usize = entries.iter().map(|e| e.replacements).sum();
+    let mut changelog = format!(
+        "## AndroidX migration\n\nMigrated {} line(s) across {} file(s).\n\n",
+        replacements,
+        entries.iter().filter(|e| e.replacements > 0).count()
+    );
+    for entry in entries {
+        changelog.push_str(&format!(
+            "- `{}`: {} replacement(s)\n",
+            entry.path.to_string_lossy(),
+            entry.replacements
+        ));
+        for artifact in &entry.artifacts {
+            changelog.push_str(&format!("  - Artifact to update: `{}`\n", artifact));
+        }
+        for star_import in &entry.star_imports {
+            changelog.push_str(&format!(
+                "  - Star import to migrate by hand: `{}`\n",
+                star_import.trim()
+            ));
+        }
+    }
+    changelog
+}
+
+/// Write the changelog to the given file, replacing anything already in it.
+///
+/// * `path` - Where to write the changelog
+/// * `entries` - What was found in each file
+pub fn write_changelog(path: &Path, entries: &[ChangelogEntry]) -> io::Result<()> {
+    fs::write(path, format_changelog(entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changelog_lists_each_file() {
+        let entries = vec![
+            ChangelogEntry {
+                path: PathBuf::from("app/src/B.java"),
+                replacements: 2,
+                star_imports: vec![String::from("  import android.support.v4.app.*;")],
+                ..Default::default()
+            },
+            ChangelogEntry {
+                path: PathBuf::from("app/build.gradle"),
+                artifacts: vec![String::from(
+                    "com.android.support:appcompat-v7 => androidx.appcompat:appcompat:1.0.0",
+                )],
+                ..Default::default()
+            },
+            ChangelogEntry {
+                path: PathBuf::from("app/src/A.java"),
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(
+            format_changelog(&entries),
+            "## AndroidX migration\n\
+             \n\
+             Migrated 2 line(s) across 1 file(s).\n\
+             \n\
+             - `app/build.gradle`: 0 replacement(s)\n  \
+             - Artifact to update: `com.android.support:appcompat-v7 => androidx.appcompat:appcompat:1.0.0`\n\
+             - `app/src/B.java`: 2 replacement(s)\n  \
+             - Star import to migrate by hand: `import android.support.v4.app.*;`\n"
+        );
+    }
+}
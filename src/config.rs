@@ -0,0 +1,234 @@
+use crate::diagnostic::DiagnosticFormat;
+use crate::finder::LoadBalance;
+use crate::report::{ArtifactReportFormat, OutputFormat};
+use encoding_rs::Encoding;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+/// The config file looked for in the current directory when one isn't explicitly given
+pub const DEFAULT_CONFIG_FILE: &str = ".rusty_jetpack.toml";
+
+/// Default options read from a TOML config file. Every field mirrors a CLI option of the same
+/// (kebab-case) name and is only used when that option isn't given on the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub quiet: Option<bool>,
+    pub verbose: Option<bool>,
+    pub threads: Option<usize>,
+    pub threads_per_core: Option<f64>,
+    pub max_file_size: Option<u64>,
+    pub load_balance: Option<LoadBalance>,
+    pub artifact_depth: Option<usize>,
+    pub no_artifact_check: Option<bool>,
+    pub artifact_check_all: Option<bool>,
+    pub color: Option<bool>,
+    pub no_color: Option<bool>,
+    pub untracked: Option<bool>,
+    pub extensions: Option<Vec<String>>,
+    #[serde(default, with = "serde_regex")]
+    pub exclude_pattern: Option<Vec<Regex>>,
+    pub min_pattern_length: Option<usize>,
+    pub regex_timeout: Option<u64>,
+    pub dry_run: Option<bool>,
+    pub check: Option<bool>,
+    pub treat_warnings_as_errors: Option<bool>,
+    pub only_files: Option<bool>,
+    pub include_artifact_files: Option<bool>,
+    pub summarize_star_imports: Option<bool>,
+    pub ignore_errors: Option<bool>,
+    pub abort_on_error: Option<bool>,
+    pub git_stage: Option<bool>,
+    pub git_commit: Option<bool>,
+    pub git_commit_amend: Option<bool>,
+    pub commit_message: Option<String>,
+    pub no_follow_symlinks: Option<bool>,
+    pub only_artifacts: Option<bool>,
+    pub io_uring: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_encoding")]
+    pub encoding: Option<&'static Encoding>,
+    pub thread_stats: Option<bool>,
+    pub profile: Option<bool>,
+    pub preserve_timestamps: Option<bool>,
+    pub max_replacements: Option<usize>,
+    pub replacement_count_threshold: Option<usize>,
+    pub interactive: Option<bool>,
+    pub submodule: Option<bool>,
+    pub staged: Option<bool>,
+    pub subdir: Option<PathBuf>,
+    pub include_sparse: Option<bool>,
+    pub use_jpmigrate: Option<bool>,
+    pub since_last_run: Option<bool>,
+    pub no_mmap: Option<bool>,
+    pub diagnostic_format: Option<DiagnosticFormat>,
+    pub output_format: Option<OutputFormat>,
+    pub artifact_report_format: Option<ArtifactReportFormat>,
+    pub suggest_bom_version: Option<bool>,
+    pub mappings_dir: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub no_tempfile: Option<bool>,
+    pub changelog: Option<PathBuf>,
+    pub watch: Option<bool>,
+    pub sort_output: Option<bool>,
+    /// The name of every option the config sets, leaving out flags that are set to false
+    #[serde(skip)]
+    pub options: Vec<String>,
+}
+
+impl Config {
+    /// Load the config from the given path, or from `.rusty_jetpack.toml` in the current directory
+    /// if no path is given. A missing default config file is treated as an empty config. Options
+    /// that are overridden by the command line or environment are left out.
+    ///
+    /// * `path` - The explicit path of the config file, if any
+    /// * `overridden` - Whether an option is overridden, given its name
+    pub fn load(path: Option<&Path>, overridden: impl Fn(&str) -> bool) -> Result<Config> {
+        let contents = match path {
+            Some(path) => fs::read_to_string(path)?,
+            None => match fs::read_to_string(DEFAULT_CONFIG_FILE) {
+                Ok(contents) => contents,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+                Err(e) => return Err(e),
+            },
+        };
+
+        Config::parse(&contents, overridden)
+    }
+
+    /// Parse the config from the contents of a TOML file.
+    ///
+    /// * `contents` - The TOML to parse
+    /// * `overridden` - Whether an option is overridden, given its name
+    fn parse(contents: &str, overridden: impl Fn(&str) -> bool) -> Result<Config> {
+        let invalid = |e| Error::new(ErrorKind::InvalidData, e);
+        let mut table: toml::Table = toml::from_str(contents).map_err(invalid)?;
+        table.retain(|name, _| !overridden(name));
+        let options = table
+            .iter()
+            .filter(|(_, value)| value.as_bool() != Some(false))
+            .map(|(name, _)| name.clone())
+            .collect();
+        let config: Config = table.try_into().map_err(invalid)?;
+
+        Ok(Config { options, ..config })
+    }
+}
+
+/// Look up a text encoding by its name, e.g. `utf-8`, `latin-1` or `windows-1252`.
+///
+/// * `label` - The name of the encoding
+///
+/// Returns the encoding if the name is known
+pub fn parse_encoding(label: &str) -> std::result::Result<&'static Encoding, String> {
+    // Also accept names like "latin-1" that are only known without the dash
+    Encoding::for_label(label.as_bytes())
+        .or_else(|| Encoding::for_label(label.replace('-', "").as_bytes()))
+        .ok_or_else(|| format!("unknown encoding {}", label))
+}
+
+/// Deserialize an optional encoding from its name.
+fn deserialize_encoding<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<&'static Encoding>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let label = String::deserialize(deserializer)?;
+    parse_encoding(&label)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_are_parsed() {
+        let config = Config::parse(
+            "threads = 4
+            dry-run = true
+            max-file-size = 1024",
+            |_| false,
+        )
+        .unwrap();
+
+        assert_eq!(config.threads, Some(4));
+        assert_eq!(config.dry_run, Some(true));
+        assert_eq!(config.max_file_size, Some(1024));
+        assert_eq!(config.quiet, None);
+    }
+
+    #[test]
+    fn empty_config_is_parsed() {
+        let config = Config::parse("", |_| false).unwrap();
+
+        assert_eq!(config.threads, None);
+        assert_eq!(config.check, None);
+    }
+
+    #[test]
+    fn unknown_option_is_an_error() {
+        let result = Config::parse("thread = 4", |_| false);
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn encoding_is_parsed() {
+        let config = Config::parse("encoding = \"latin-1\"", |_| false).unwrap();
+
+        assert_eq!(config.encoding, Some(encoding_rs::WINDOWS_1252));
+    }
+
+    #[test]
+    fn unknown_encoding_is_an_error() {
+        let result = Config::parse("encoding = \"klingon\"", |_| false);
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn exclude_patterns_are_parsed() {
+        let config =
+            Config::parse("exclude-pattern = [\"^\\\\s*//\", \"@Keep\"]", |_| false).unwrap();
+        let patterns = config.exclude_pattern.unwrap();
+
+        assert_eq!(patterns.len(), 2);
+        assert!(patterns[0].is_match("  // android.support.v4.app.Fragment"));
+    }
+
+    #[test]
+    fn invalid_exclude_pattern_is_an_error() {
+        let result = Config::parse("exclude-pattern = [\"(\"]", |_| false);
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn overridden_options_are_left_out() {
+        let config = Config::parse(
+            "threads = 4
+            quiet = true
+            dry-run = false",
+            |name| name == "threads",
+        )
+        .unwrap();
+
+        assert_eq!(config.threads, None);
+        assert_eq!(config.quiet, Some(true));
+        assert_eq!(config.dry_run, Some(false));
+        assert_eq!(config.options, vec![String::from("quiet")]);
+    }
+
+    #[test]
+    fn missing_explicit_config_is_an_error() {
+        let result = Config::load(Some(Path::new("does-not-exist.toml")), |_| false);
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,97 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::json;
+
+use std::path::Path;
+
+/// Machine readable formats for warnings about specific lines, for editors and other tools
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticFormat {
+    /// `<file>:<line>: warning: <message>`, the same as GCC and clang
+    Gcc,
+    /// One JSON object per line in the same shape as rustc's `--error-format json`
+    Json,
+}
+
+/// Format a warning about a single line of a file.
+///
+/// * `format` - The format to use
+/// * `path` - The path of the file
+/// * `line_number` - The 1-based line number the warning is for
+/// * `message` - What the warning is about
+///
+/// Returns the formatted warning, without a trailing new line
+pub fn format_warning(
+    format: DiagnosticFormat,
+    path: &Path,
+    line_number: usize,
+    message: &str,
+) -> String {
+    let gcc = format!(
+        "{}:{}: warning: {}",
+        path.to_string_lossy(),
+        line_number,
+        message
+    );
+    match format {
+        DiagnosticFormat::Gcc => gcc,
+        DiagnosticFormat::Json => json!({
+            "$message_type": "diagnostic",
+            "message": message,
+            "code": null,
+            "level": "warning",
+            "spans": [{
+                "file_name": path.to_string_lossy(),
+                "line_start": line_number,
+                "line_end": line_number,
+                "column_start": 1,
+                "column_end": 1,
+                "is_primary": true,
+            }],
+            "children": [],
+            "rendered": gcc,
+        })
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gcc_warning_is_formatted() {
+        let warning = format_warning(
+            DiagnosticFormat::Gcc,
+            Path::new("app/src/Main.java"),
+            3,
+            "star import must be migrated by hand",
+        );
+
+        assert_eq!(
+            warning,
+            "app/src/Main.java:3: warning: star import must be migrated by hand"
+        );
+    }
+
+    #[test]
+    fn json_warning_is_formatted() {
+        let warning = format_warning(
+            DiagnosticFormat::Json,
+            Path::new("app/src/Main.java"),
+            3,
+            "star import must be migrated by hand",
+        );
+        let value: serde_json::Value = serde_json::from_str(&warning).unwrap();
+
+        assert_eq!(value["level"], "warning");
+        assert_eq!(value["message"], "star import must be migrated by hand");
+        assert_eq!(value["spans"][0]["file_name"], "app/src/Main.java");
+        assert_eq!(value["spans"][0]["line_start"], 3);
+        assert_eq!(
+            value["rendered"],
+            "app/src/Main.java:3: warning: star import must be migrated by hand"
+        );
+    }
+}
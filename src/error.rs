@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The different ways migrating a single file can fail.
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The file couldn't be opened or its metadata couldn't be read
+    Open(PathBuf, io::Error),
+    /// The contents of the file couldn't be read
+    Read(PathBuf, io::Error),
+    /// The contents of the file aren't valid UTF-8
+    Decode(PathBuf),
+    /// The migrated contents couldn't be written to a temporary file
+    Write(PathBuf, io::Error),
+    /// The original file couldn't be replaced with the migrated one
+    Persist(PathBuf, io::Error),
+    /// The changes weren't written since they would go over the max number of replacements
+    TooManyReplacements(PathBuf),
+    /// Any other I/O error
+    Io(io::Error),
+}
+
+impl MigrationError {
+    /// Create the error for a failed read, telling apart undecodable contents from other errors.
+    ///
+    /// * `path` - The file being read
+    /// * `error` - The error from reading
+    pub fn read(path: &Path, error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::InvalidData {
+            MigrationError::Decode(path.to_path_buf())
+        } else {
+            MigrationError::Read(path.to_path_buf(), error)
+        }
+    }
+
+    /// The path of the file that failed to migrate, if the error is for a single file.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            MigrationError::Open(path, _)
+            | MigrationError::Read(path, _)
+            | MigrationError::Decode(path)
+            | MigrationError::Write(path, _)
+            | MigrationError::Persist(path, _)
+            | MigrationError::TooManyReplacements(path) => Some(path),
+            MigrationError::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MigrationError::Open(path, e) => {
+                write!(f, "Failed to open {}: {}", path.to_string_lossy(), e)
+            }
+            MigrationError::Read(path, e) => {
+                write!(f, "Failed to read {}: {}", path.to_string_lossy(), e)
+            }
+            MigrationError::Decode(path) => {
+                write!(
+                    f,
+                    "Failed to read {}: not valid UTF-8",
+                    path.to_string_lossy()
+                )
+            }
+            MigrationError::Write(path, e) => write!(
+                f,
+                "Failed to write changes for {}: {}",
+                path.to_string_lossy(),
+                e
+            ),
+            MigrationError::Persist(path, e) => {
+                write!(f, "Failed to replace {}: {}", path.to_string_lossy(), e)
+            }
+            MigrationError::TooManyReplacements(path) => write!(
+                f,
+                "Skipped writing changes for {}: over the max number of replacements",
+                path.to_string_lossy()
+            ),
+            MigrationError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for MigrationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MigrationError::Open(_, e)
+            | MigrationError::Read(_, e)
+            | MigrationError::Write(_, e)
+            | MigrationError::Persist(_, e)
+            | MigrationError::Io(e) => Some(e),
+            MigrationError::Decode(_) | MigrationError::TooManyReplacements(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for MigrationError {
+    fn from(error: io::Error) -> Self {
+        MigrationError::Io(error)
+    }
+}
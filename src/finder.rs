@@ -1,17 +1,234 @@
+use crate::state::State;
+use clap::ValueEnum;
 use crossbeam_channel::Sender;
-use std::path::PathBuf;
-use std::process::Command;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use serde::Deserialize;
+use std::cmp::max;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 pub struct FinderInfo {
     pub total_files_found: usize,
+    /// Number of files skipped since they haven't changed since the last run
+    pub unchanged_files: usize,
+    /// The largest file found along with its size in bytes, only known when balancing by size
+    pub largest_file: Option<(PathBuf, u64)>,
+    #[allow(dead_code)]
     pub num_files_per_matcher: Vec<usize>,
 }
 
-pub struct Finder;
+/// Endings of the non-binary files that will actually contain anything to change
+const BASE_EXTENSIONS: &[&str] = &[
+    ".kt",
+    ".java",
+    ".xml",
+    ".pro",
+    ".gradle",
+    "gradle.kts",
+    ".aidl",
+];
+
+/// Files larger than this many bytes are sent to their own matchers when balancing by size
+const LARGE_FILE_SIZE: u64 = 100 * 1024;
+
+/// File listing the paths to migrate one per line for projects that aren't managed by git
+const MARKER_FILE: &str = ".rusty_jetpack";
+
+/// File listing globs of the paths to migrate one per line, used instead of git with --use-jpmigrate
+pub const JPMIGRATE_FILE: &str = ".jpmigrate";
+
+/// Lines of output that each contain a path relative to the root of the project
+type PathLines = Box<dyn Iterator<Item = io::Result<String>>>;
+
+/// How files are spread across the matchers
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoadBalance {
+    /// Each matcher gets the next file in turn
+    #[default]
+    RoundRobin,
+    /// Files over 100 KB go to a quarter of the matchers set aside for them and the rest are
+    /// spread across the others in turn, so a few large files can't hold up the small ones
+    Size,
+}
+
+/// Options that change which files the Finder picks up.
+#[derive(Clone, Debug, Default)]
+pub struct FinderOptions {
+    /// Also include untracked files that aren't ignored by git
+    pub untracked: bool,
+    /// Extensions of files to include on top of the default ones
+    pub extensions: Vec<String>,
+    /// Also include files in git submodules
+    pub submodules: bool,
+    /// Only include files matching the globs in the .jpmigrate file instead of asking git
+    pub use_jpmigrate: bool,
+    /// Only include files with changes staged in git
+    pub staged: bool,
+    /// Only include files under this directory, relative to the root of the project
+    pub subdir: Option<PathBuf>,
+    /// How files are spread across the matchers
+    pub load_balance: LoadBalance,
+    /// What was migrated on the last run, files that haven't changed since are skipped
+    pub last_run: Option<State>,
+    /// Set once the run is aborted, no more files are sent to the matchers after that
+    pub aborted: Arc<AtomicBool>,
+}
+
+/// The patterns of a sparse checkout that was expanded to the full repo with --include-sparse, so
+/// it can be restored once finished. It's also restored when dropped without being restored, e.g.
+/// when a run is cut short, so the checkout is never left expanded.
+pub struct SparseCheckout {
+    patterns: Vec<String>,
+    cone: bool,
+    restored: bool,
+}
+
+impl SparseCheckout {
+    /// Expand the sparse checkout of the current repo to every file, remembering its patterns.
+    pub fn expand() -> io::Result<Self> {
+        let output = Command::new("git")
+            .args(["sparse-checkout", "list"])
+            .stderr(Stdio::inherit())
+            .output()?;
+        check_status("git sparse-checkout list", output.status)?;
+        let patterns = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect();
+        let cone = git_config_bool(Path::new("."), "core.sparseCheckoutCone");
+
+        let status = Command::new("git")
+            .args(["sparse-checkout", "disable"])
+            .status()?;
+        check_status("git sparse-checkout disable", status)?;
+        Ok(SparseCheckout {
+            patterns,
+            cone,
+            restored: false,
+        })
+    }
+
+    /// Go back to the sparse checkout as it was before being expanded. Files outside of it that
+    /// were migrated are modified so git leaves them in place.
+    pub fn restore(mut self) -> io::Result<()> {
+        self.restored = true;
+        self.set_patterns()
+    }
+
+    /// Set the sparse checkout back to the remembered patterns.
+    fn set_patterns(&self) -> io::Result<()> {
+        let status = Command::new("git")
+            .args(["sparse-checkout", "set"])
+            .arg(if self.cone { "--cone" } else { "--no-cone" })
+            .args(&self.patterns)
+            .status()?;
+        check_status("git sparse-checkout set", status)
+    }
+}
+
+impl Drop for SparseCheckout {
+    fn drop(&mut self) {
+        if !self.restored {
+            if let Err(e) = self.set_patterns() {
+                eprintln!("Failed to restore the sparse checkout: {}", e);
+            }
+        }
+    }
+}
+
+pub struct Finder {
+    options: FinderOptions,
+}
 
 impl Finder {
-    pub fn new() -> Self {
-        Finder
+    /// Create a Finder
+    ///
+    /// * `options` - The options to find files with
+    pub fn new(options: FinderOptions) -> Self {
+        Finder { options }
+    }
+
+    /// Lazily find all applicable files. Paths are yielded as soon as git outputs them so they
+    /// can be worked on before the full list of files is known.
+    pub fn iter(&self) -> FinderIter {
+        // Get all the files from git so we don't have to worry about going through files that the
+        // project doesn't even care about, e.g. files in the "build" directory. Projects that
+        // aren't managed by git can list their files in a marker file instead, otherwise fall back
+        // to walking the directory while still respecting any .gitignore files.
+        let mut queries = VecDeque::new();
+        let mut lines = None;
+        let mut skip_missing = false;
+        if self.options.use_jpmigrate {
+            lines = Some(jpmigrate_paths(Path::new(JPMIGRATE_FILE), Path::new(".")));
+        } else if is_git_repo(Path::new(".")) {
+            // Files outside of a sparse checkout are still listed but aren't on disk
+            skip_missing = is_sparse_checkout(Path::new("."));
+            if self.options.staged {
+                // Staged deletions have nothing left to migrate, and paths are kept relative to
+                // the current directory the same as `git ls-files`
+                queries.push_back(vec![
+                    "diff",
+                    "--name-only",
+                    "--cached",
+                    "--relative",
+                    "--diff-filter=d",
+                ]);
+            } else if self.options.submodules {
+                // Submodule files are listed with the submodule's path as a prefix, the same as
+                // running `git ls-files` in each of them with `git submodule foreach --recursive`
+                queries.push_back(vec!["ls-files", "--recurse-submodules"]);
+            } else {
+                queries.push_back(vec!["ls-files"]);
+            }
+            if self.options.untracked && !self.options.staged {
+                // Newly created files haven't been added to the index yet, so ask for those as
+                // well while still respecting any ignore rules.
+                queries.push_back(vec!["ls-files", "--others", "--exclude-standard"]);
+            }
+        } else if Path::new(MARKER_FILE).is_file() {
+            lines = Some(marker_file_paths(Path::new(MARKER_FILE)));
+        } else {
+            lines = Some(walk_paths(Path::new(".")));
+        }
+
+        FinderIter {
+            queries,
+            extensions: self.extensions(),
+            subdir: self.options.subdir.clone(),
+            child: None,
+            lines,
+            skip_missing,
+        }
+    }
+
+    /// Whether the file at the given path has the extension of a file that could need migrating.
+    ///
+    /// * `path` - The path of the file
+    pub fn has_migratable_extension(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        self.extensions().iter().any(|e| path.ends_with(e.as_str()))
+    }
+
+    /// The endings of every file to include, the default ones and any extras.
+    fn extensions(&self) -> Vec<String> {
+        // Make sure to only match full extensions so "properties" doesn't pick up
+        // "foo.notproperties".
+        let mut extensions: Vec<String> = BASE_EXTENSIONS.iter().map(|e| e.to_string()).collect();
+        extensions.extend(self.options.extensions.iter().map(|e| {
+            if e.starts_with('.') {
+                e.clone()
+            } else {
+                format!(".{}", e)
+            }
+        }));
+        extensions
     }
 
     /// Find all applicable files and transmit them with the given list of channels.
@@ -19,33 +236,96 @@ impl Finder {
     /// * `matcher_txs` - A vector of transmitters for the different matcher threads
     /// * `tx_info` - A trasmitter back to the main thread to report info
     pub fn find_paths(&self, matcher_txs: Vec<Sender<PathBuf>>, tx_info: Sender<FinderInfo>) {
-        // Get all the files from git so we don't have to worry about going through files that the
-        // project doesn't even care about, e.g. files in the "build" directory.
-        let output = Command::new("git")
-            .arg("ls-files")
-            .output()
-            .expect("Failed to execute `git ls-files`! Are you in a git repo?")
-            .stdout;
+        self.find_paths_filtered(matcher_txs, tx_info, |_| true);
+    }
+
+    /// Find all applicable files that also match the given predicate and transmit them with the
+    /// given list of channels.
+    ///
+    /// * `matcher_txs` - A vector of transmitters for the different matcher threads
+    /// * `tx_info` - A trasmitter back to the main thread to report info
+    /// * `predicate` - Any extra check a file has to pass on top of having a migratable extension
+    pub fn find_paths_filtered<F: Fn(&Path) -> bool>(
+        &self,
+        matcher_txs: Vec<Sender<PathBuf>>,
+        tx_info: Sender<FinderInfo>,
+        predicate: F,
+    ) {
+        self.send_paths(self.iter(), matcher_txs, tx_info, predicate);
+    }
 
+    /// Transmit the given paths that match the predicate with the given list of channels.
+    ///
+    /// * `paths` - The paths of the applicable files
+    /// * `matcher_txs` - A vector of transmitters for the different matcher threads
+    /// * `tx_info` - A trasmitter back to the main thread to report info
+    /// * `predicate` - Any extra check a file has to pass
+    fn send_paths<F: Fn(&Path) -> bool>(
+        &self,
+        paths: impl Iterator<Item = PathBuf>,
+        matcher_txs: Vec<Sender<PathBuf>>,
+        tx_info: Sender<FinderInfo>,
+        predicate: F,
+    ) {
         let mut files_found = 0;
+        let mut unchanged_files = 0;
         let mut matcher_thread = 0;
         let mut files_per_thread: Vec<usize> = vec![0; matcher_txs.len()];
-        String::from_utf8(output)
-            .unwrap()
-            .lines()
-            .filter(|f| {
-                // Filter on non-binary files that will actually contain anything to change
-                f.ends_with(".kt")
-                    || f.ends_with(".java")
-                    || f.ends_with(".xml")
-                    || f.ends_with(".pro")
-                    || f.ends_with(".gradle")
-                    || f.ends_with("gradle.kts")
-            })
-            .map(PathBuf::from)
+        let mut largest_file: Option<(PathBuf, u64)> = None;
+        // When balancing by size the first matchers are set aside for large files, but only once
+        // one is found so they aren't left idle in projects without any
+        let by_size = self.options.load_balance == LoadBalance::Size;
+        let large_file_threads = if by_size && matcher_txs.len() > 1 {
+            max(1, matcher_txs.len() / 4)
+        } else {
+            0
+        };
+        let mut large_file_thread = 0;
+        let mut found_large_file = false;
+        let aborted = &self.options.aborted;
+        paths
+            .take_while(|_| !aborted.load(Ordering::SeqCst))
+            .filter(|f| predicate(f))
             .for_each(|f| {
+                if self
+                    .options
+                    .last_run
+                    .as_ref()
+                    .is_some_and(|state| state.is_unchanged(&f))
+                {
+                    unchanged_files += 1;
+                    return;
+                }
+
+                let size = if by_size {
+                    fs::metadata(&f).map_or(0, |metadata| metadata.len())
+                } else {
+                    0
+                };
+                if by_size
+                    && largest_file
+                        .as_ref()
+                        .is_none_or(|(_, largest)| size > *largest)
+                {
+                    largest_file = Some((f.clone(), size));
+                }
+
+                files_found += 1;
+                if large_file_threads > 0 && size > LARGE_FILE_SIZE {
+                    found_large_file = true;
+                    // Matchers stop receiving once the run is aborted
+                    let _ = matcher_txs[large_file_thread].send(f);
+                    files_per_thread[large_file_thread] += 1;
+                    large_file_thread = (large_file_thread + 1) % large_file_threads;
+                    return;
+                }
+                if found_large_file && matcher_thread < large_file_threads {
+                    matcher_thread = large_file_threads;
+                }
+
                 // Send the path in a matcher's channel
-                matcher_txs[matcher_thread].send(f).unwrap();
+                // Matchers stop receiving once the run is aborted
+                let _ = matcher_txs[matcher_thread].send(f);
                 // Share the love across all the threads
                 files_per_thread[matcher_thread] += 1;
                 matcher_thread = if matcher_thread == matcher_txs.len() - 1 {
@@ -53,11 +333,427 @@ impl Finder {
                 } else {
                     matcher_thread + 1
                 };
-                files_found += 1;
             });
         let _ = tx_info.send(FinderInfo {
             total_files_found: files_found,
+            unchanged_files,
+            largest_file,
             num_files_per_matcher: files_per_thread,
         });
     }
 }
+
+/// Iterator over all applicable files, created by [`Finder::iter`].
+pub struct FinderIter {
+    /// Arguments for the git calls that haven't been started yet
+    queries: VecDeque<Vec<&'static str>>,
+    /// Endings of the files to include
+    extensions: Vec<String>,
+    /// The directory files have to be under to be included, if any
+    subdir: Option<PathBuf>,
+    /// The currently running git call
+    child: Option<Child>,
+    /// The output of the currently running git call, or of the fallback when the project isn't
+    /// managed by git
+    lines: Option<PathLines>,
+    /// Leave out files that don't exist, e.g. the ones outside of a sparse checkout
+    skip_missing: bool,
+}
+
+impl FinderIter {
+    /// Whether the file at the given path could contain anything to migrate.
+    ///
+    /// * `path` - The path of the file relative to the root of the repo
+    fn is_migratable(&self, path: &str) -> bool {
+        self.extensions.iter().any(|e| path.ends_with(e.as_str()))
+            && self
+                .subdir
+                .as_ref()
+                .is_none_or(|dir| Path::new(path).starts_with(dir))
+            && (!self.skip_missing || Path::new(path).exists())
+    }
+}
+
+impl Iterator for FinderIter {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        loop {
+            if let Some(lines) = &mut self.lines {
+                match lines.next() {
+                    Some(Ok(line)) if self.is_migratable(&line) => {
+                        return Some(PathBuf::from(line))
+                    }
+                    Some(Ok(_)) => continue,
+                    _ => {
+                        // Clean up the finished call before moving on to the next one
+                        self.lines = None;
+                        if let Some(mut child) = self.child.take() {
+                            let _ = child.wait();
+                        }
+                    }
+                }
+            }
+
+            let args = self.queries.pop_front()?;
+            let mut child = git_list_files(Path::new("."), &args);
+            self.lines = child
+                .stdout
+                .take()
+                .map(|stdout| Box::new(BufReader::new(stdout).lines()) as PathLines);
+            self.child = Some(child);
+        }
+    }
+}
+
+/// Start a git command that lists files one per line, e.g. `git ls-files`, with its output piped
+/// back to us.
+///
+/// * `dir` - The directory to list the files of
+/// * `args` - The git command and its arguments
+fn git_list_files(dir: &Path, args: &[&str]) -> Child {
+    Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|_| {
+            panic!(
+                "Failed to execute `git {}`! Are you in a git repo?",
+                args[0]
+            )
+        })
+}
+
+/// Whether the given directory is inside of a git repo. Worktrees created with `git worktree add`
+/// count as well since git finds the repo through their `.git` file.
+///
+/// * `dir` - The directory to check
+fn is_git_repo(dir: &Path) -> bool {
+    Command::new("git")
+        .current_dir(dir)
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Whether the repo in the given directory is a sparse checkout, so only some of its files are on
+/// disk.
+///
+/// * `dir` - The directory of the repo
+pub fn is_sparse_checkout(dir: &Path) -> bool {
+    git_config_bool(dir, "core.sparseCheckout")
+}
+
+/// Whether the given boolean git config option is set to true.
+///
+/// * `dir` - The directory of the repo
+/// * `key` - The name of the option
+fn git_config_bool(dir: &Path, key: &str) -> bool {
+    Command::new("git")
+        .current_dir(dir)
+        .args(["config", "--bool", key])
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|output| output.stdout.starts_with(b"true"))
+}
+
+/// Turn an unsuccessful exit status of a git command into an error.
+///
+/// * `command` - The git command that was run
+/// * `status` - The exit status of the command
+fn check_status(command: &str, status: ExitStatus) -> io::Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`{}` exited with {}",
+            command, status
+        )))
+    }
+}
+
+/// Read the paths listed in a marker file, skipping blank lines and comments starting with `#`.
+///
+/// * `path` - The path of the marker file
+fn marker_file_paths(path: &Path) -> PathLines {
+    let file = File::open(path).expect("Failed to read the .rusty_jetpack marker file!");
+    Box::new(
+        BufReader::new(file)
+            .lines()
+            .map(|line| line.map(|line| String::from(line.trim())))
+            .filter(|line| !matches!(line, Ok(line) if line.is_empty() || line.starts_with('#'))),
+    )
+}
+
+/// Find every file under the given directory matching one of the globs listed in a .jpmigrate
+/// file, skipping blank lines and comments starting with `#`.
+///
+/// * `path` - The path of the .jpmigrate file
+/// * `root` - The directory to match the globs against
+fn jpmigrate_paths(path: &Path, root: &Path) -> PathLines {
+    let contents = fs::read_to_string(path).expect("Failed to read the .jpmigrate file!");
+    let globs = jpmigrate_globs(&contents);
+    Box::new(walk_paths(root).filter(move |line| match line {
+        Ok(line) => globs.is_match(line),
+        Err(_) => true,
+    }))
+}
+
+/// Build the set of globs listed one per line in the contents of a .jpmigrate file.
+///
+/// * `contents` - The contents of the .jpmigrate file
+fn jpmigrate_globs(contents: &str) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .for_each(|line| {
+            let glob = Glob::new(line)
+                .unwrap_or_else(|e| panic!("Invalid glob in the .jpmigrate file: {}", e));
+            builder.add(glob);
+        });
+    builder
+        .build()
+        .expect("Failed to build the globs in the .jpmigrate file!")
+}
+
+/// Recursively find every file under the given directory that isn't ignored by a .gitignore or
+/// .ignore file.
+///
+/// * `root` - The directory to start walking from
+fn walk_paths(root: &Path) -> PathLines {
+    let root = root.to_path_buf();
+    Box::new(
+        WalkBuilder::new(&root)
+            .require_git(false)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .map(move |entry| {
+                let path = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+                Ok(path.to_string_lossy().into_owned())
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aidl_files_are_migratable() {
+        let iter = Finder::new(FinderOptions::default()).iter();
+
+        assert!(iter.is_migratable("src/main/aidl/com/example/IService.aidl"));
+        assert!(!iter.is_migratable("src/main/aidl/com/example/IService.notaidl"));
+    }
+
+    #[test]
+    fn only_files_in_subdir_are_migratable() {
+        let iter = Finder::new(FinderOptions {
+            subdir: Some(PathBuf::from("libs/core")),
+            ..Default::default()
+        })
+        .iter();
+
+        assert!(iter.is_migratable("libs/core/src/main/java/A.java"));
+        assert!(!iter.is_migratable("libs/core-ktx/src/main/java/A.java"));
+        assert!(!iter.is_migratable("app/src/main/java/A.java"));
+    }
+
+    #[test]
+    fn extra_extensions_are_migratable() {
+        let finder = Finder::new(FinderOptions {
+            extensions: vec![String::from("properties")],
+            ..Default::default()
+        });
+
+        assert!(finder.has_migratable_extension(Path::new("gradle.properties")));
+        assert!(finder.has_migratable_extension(Path::new("src/A.java")));
+        assert!(!finder.has_migratable_extension(Path::new("foo.notproperties.txt")));
+    }
+
+    #[test]
+    fn predicate_filters_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MARKER_FILE);
+        fs::write(&path, "src/A.java\ntest/B.java\n").unwrap();
+        let finder = Finder::new(FinderOptions::default());
+        let iter = FinderIter {
+            queries: VecDeque::new(),
+            extensions: vec![String::from(".java")],
+            subdir: None,
+            child: None,
+            lines: Some(marker_file_paths(&path)),
+            skip_missing: false,
+        };
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let (tx_info, rx_info) = crossbeam_channel::unbounded();
+
+        finder.send_paths(iter, vec![tx], tx_info, |path| path.starts_with("src"));
+
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("src/A.java")]
+        );
+        assert_eq!(rx_info.recv().unwrap().total_files_found, 1);
+    }
+
+    #[test]
+    fn large_files_get_their_own_matchers_when_balancing_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let small: Vec<PathBuf> = (0..4)
+            .map(|i| {
+                let path = dir.path().join(format!("Small{}.java", i));
+                fs::write(&path, "class Small {}\n").unwrap();
+                path
+            })
+            .collect();
+        let large = dir.path().join("large.xml");
+        fs::write(&large, vec![b' '; LARGE_FILE_SIZE as usize + 1]).unwrap();
+        let finder = Finder::new(FinderOptions {
+            load_balance: LoadBalance::Size,
+            ..Default::default()
+        });
+        let (txs, rxs): (Vec<_>, Vec<_>) = (0..4).map(|_| crossbeam_channel::unbounded()).unzip();
+        let (tx_info, rx_info) = crossbeam_channel::unbounded();
+
+        let paths = vec![
+            small[0].clone(),
+            large.clone(),
+            small[1].clone(),
+            small[2].clone(),
+            small[3].clone(),
+        ];
+        finder.send_paths(paths.into_iter(), txs, tx_info, |_| true);
+
+        // The first small file is sent before any large file is found
+        assert_eq!(
+            rxs[0].try_iter().collect::<Vec<_>>(),
+            vec![small[0].clone(), large.clone()]
+        );
+        assert_eq!(
+            rxs[1].try_iter().collect::<Vec<_>>(),
+            vec![small[1].clone()]
+        );
+        assert_eq!(
+            rxs[2].try_iter().collect::<Vec<_>>(),
+            vec![small[2].clone()]
+        );
+        assert_eq!(
+            rxs[3].try_iter().collect::<Vec<_>>(),
+            vec![small[3].clone()]
+        );
+        let info = rx_info.recv().unwrap();
+        assert_eq!(info.total_files_found, 5);
+        assert_eq!(info.largest_file, Some((large, LARGE_FILE_SIZE + 1)));
+    }
+
+    #[test]
+    fn submodules_are_listed_recursively() {
+        let options = FinderOptions {
+            submodules: true,
+            ..Default::default()
+        };
+        let iter = Finder::new(options).iter();
+
+        assert_eq!(
+            iter.queries.front(),
+            Some(&vec!["ls-files", "--recurse-submodules"])
+        );
+    }
+
+    #[test]
+    fn staged_files_are_listed_from_the_index() {
+        let options = FinderOptions {
+            staged: true,
+            untracked: true,
+            ..Default::default()
+        };
+        let iter = Finder::new(options).iter();
+
+        assert_eq!(iter.queries.len(), 1);
+        assert_eq!(iter.queries[0][..3], ["diff", "--name-only", "--cached"]);
+    }
+
+    #[test]
+    fn worktree_files_are_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let worktree = dir.path().join("worktree");
+        fs::create_dir_all(repo.join("src")).unwrap();
+        fs::write(repo.join("src/A.java"), "").unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .current_dir(&repo)
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        git(&["add", "src/A.java"]);
+        git(&["commit", "-q", "-m", "Initial commit"]);
+        git(&["worktree", "add", "-q", worktree.to_str().unwrap()]);
+
+        let mut child = git_list_files(&worktree, &["ls-files"]);
+        let paths: Vec<String> = BufReader::new(child.stdout.take().unwrap())
+            .lines()
+            .map(Result::unwrap)
+            .collect();
+        child.wait().unwrap();
+
+        assert!(is_git_repo(&worktree));
+        assert_eq!(paths, vec!["src/A.java"]);
+    }
+
+    #[test]
+    fn marker_file_skips_blank_lines_and_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MARKER_FILE);
+        fs::write(&path, "# Sources\nsrc/A.java\n\n  src/B.kt  \n").unwrap();
+
+        let paths: Vec<String> = marker_file_paths(&path).map(Result::unwrap).collect();
+
+        assert_eq!(paths, vec!["src/A.java", "src/B.kt"]);
+    }
+
+    #[test]
+    fn jpmigrate_only_includes_matching_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(JPMIGRATE_FILE);
+        fs::create_dir_all(dir.path().join("app/src")).unwrap();
+        fs::create_dir_all(dir.path().join("lib/src")).unwrap();
+        fs::write(&path, "# Only the app for now\napp/**/*.java\n\n").unwrap();
+        fs::write(dir.path().join("app/src/A.java"), "").unwrap();
+        fs::write(dir.path().join("app/src/B.kt"), "").unwrap();
+        fs::write(dir.path().join("lib/src/C.java"), "").unwrap();
+
+        let paths: Vec<String> = jpmigrate_paths(&path, dir.path())
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(paths, vec!["app/src/A.java"]);
+    }
+
+    #[test]
+    fn walk_respects_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("build")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "build/\n").unwrap();
+        fs::write(dir.path().join("src/A.java"), "").unwrap();
+        fs::write(dir.path().join("build/B.java"), "").unwrap();
+
+        let paths: Vec<String> = walk_paths(dir.path()).map(Result::unwrap).collect();
+
+        assert_eq!(paths, vec!["src/A.java"]);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::matcher::ReplacedLine;
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The answer given when asked whether to apply the changes to a file
+#[derive(Debug, PartialEq)]
+enum Answer {
+    /// Apply the changes to this file
+    Yes,
+    /// Leave this file as is
+    No,
+    /// Apply the changes to this file and every remaining file without asking
+    All,
+    /// Leave this file and every remaining file as is
+    Quit,
+}
+
+impl Answer {
+    /// Parse the answer from a line of input, defaulting to no.
+    ///
+    /// * `input` - The line the user entered
+    fn parse(input: &str) -> Self {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => Answer::Yes,
+            "a" | "all" => Answer::All,
+            "q" | "quit" => Answer::Quit,
+            _ => Answer::No,
+        }
+    }
+}
+
+/// Write out the lines that were replaced in a file as a simple diff.
+///
+/// * `output` - Where to write the diff to
+/// * `path` - The path of the file
+/// * `lines` - The lines that were replaced in the file
+pub fn write_diff<W: Write>(output: &mut W, path: &Path, lines: &[ReplacedLine]) -> io::Result<()> {
+    for line in lines {
+        writeln!(output, "{}:{}", path.to_string_lossy(), line.line_number)?;
+        writeln!(output, "- {}", line.old)?;
+        writeln!(output, "+ {}", line.new)?;
+        if let Some(description) = &line.description {
+            writeln!(output, "  note: {}", description)?;
+        }
+    }
+    Ok(())
+}
+
+/// Show the proposed changes for each file and ask whether to apply them. Running out of input
+/// is treated the same as quitting.
+///
+/// * `input` - Where to read answers from
+/// * `output` - Where to write the diffs and prompts to
+/// * `proposed` - Each file that would be changed along with its replaced lines
+///
+/// Returns the paths of the files that the changes should be applied to
+pub fn confirm_changes<R: BufRead, W: Write>(
+    mut input: R,
+    mut output: W,
+    proposed: &[(PathBuf, Vec<ReplacedLine>)],
+) -> io::Result<Vec<PathBuf>> {
+    let mut confirmed = Vec::new();
+    for (i, (path, lines)) in proposed.iter().enumerate() {
+        write_diff(&mut output, path, lines)?;
+        write!(output, "Apply changes? [y/N/a/q] ")?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            writeln!(output)?;
+            break;
+        }
+        match Answer::parse(&answer) {
+            Answer::Yes => confirmed.push(path.clone()),
+            Answer::No => {}
+            Answer::All => {
+                confirmed.extend(proposed[i..].iter().map(|(path, _)| path.clone()));
+                break;
+            }
+            Answer::Quit => break,
+        }
+    }
+    Ok(confirmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposed(paths: &[&str]) -> Vec<(PathBuf, Vec<ReplacedLine>)> {
+        paths
+            .iter()
+            .map(|path| {
+                let line = ReplacedLine {
+                    line_number: 1,
+                    old: String::from("import android.support.annotation.NonNull;"),
+                    new: String::from("import androidx.annotation.NonNull;"),
+                    description: None,
+                };
+                (PathBuf::from(path), vec![line])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn answers_are_parsed() {
+        assert_eq!(Answer::parse("y\n"), Answer::Yes);
+        assert_eq!(Answer::parse("YES\n"), Answer::Yes);
+        assert_eq!(Answer::parse("\n"), Answer::No);
+        assert_eq!(Answer::parse("a\n"), Answer::All);
+        assert_eq!(Answer::parse("q\n"), Answer::Quit);
+        assert_eq!(Answer::parse("what\n"), Answer::No);
+    }
+
+    #[test]
+    fn only_confirmed_files_are_returned() {
+        let proposed = proposed(&["A.java", "B.java", "C.java"]);
+        let mut output = Vec::new();
+
+        let confirmed = confirm_changes("y\nn\ny\n".as_bytes(), &mut output, &proposed).unwrap();
+
+        assert_eq!(
+            confirmed,
+            vec![PathBuf::from("A.java"), PathBuf::from("C.java")]
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(
+            "A.java:1
+- import android.support.annotation.NonNull;
++ import androidx.annotation.NonNull;
+Apply changes? [y/N/a/q] "
+        ));
+    }
+
+    #[test]
+    fn all_confirms_remaining_files() {
+        let proposed = proposed(&["A.java", "B.java", "C.java"]);
+
+        let confirmed = confirm_changes("n\na\n".as_bytes(), Vec::new(), &proposed).unwrap();
+
+        assert_eq!(
+            confirmed,
+            vec![PathBuf::from("B.java"), PathBuf::from("C.java")]
+        );
+    }
+
+    #[test]
+    fn quit_keeps_previous_answers() {
+        let proposed = proposed(&["A.java", "B.java", "C.java"]);
+
+        let confirmed = confirm_changes("y\nq\ny\n".as_bytes(), Vec::new(), &proposed).unwrap();
+
+        assert_eq!(confirmed, vec![PathBuf::from("A.java")]);
+    }
+
+    #[test]
+    fn end_of_input_stops_asking() {
+        let proposed = proposed(&["A.java", "B.java"]);
+
+        let confirmed = confirm_changes("y\n".as_bytes(), Vec::new(), &proposed).unwrap();
+
+        assert_eq!(confirmed, vec![PathBuf::from("A.java")]);
+    }
+}
@@ -1,24 +1,75 @@
-use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use changelog::ChangelogEntry;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap_complete::Shell;
+use config::Config;
+use crossbeam_channel::{bounded, never, select, unbounded, Receiver, Sender};
+use diagnostic::DiagnosticFormat;
+use encoding_rs::Encoding;
+use error::MigrationError;
+use finder::{FinderInfo, FinderOptions, LoadBalance, SparseCheckout};
+use ignore::gitignore::Gitignore;
 use lazy_static::lazy_static;
-use matcher::MatchInfo;
-use structopt::StructOpt;
+use mappings::Category;
+use matcher::{MatchInfo, Matcher, MatcherOptions, ReplacedLine, WarningKind};
+use notify::{EventKind, RecursiveMode, Watcher};
+use regex::{Regex, RegexSet};
+use report::{ArtifactReportFormat, FileSummary, JsonReport, OutputFormat};
+use sha2::{Digest, Sha256};
+use state::State;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
-use std::cmp::min;
-use std::io::Result;
-use std::path::PathBuf;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::io::prelude::*;
+use std::io::{self, IsTerminal};
+use std::path::{Component, Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 
+mod bom;
+mod changelog;
+mod config;
+mod diagnostic;
+mod error;
 mod finder;
+mod interactive;
 mod mappings;
 mod matcher;
+mod report;
+mod state;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
 
 lazy_static! {
     static ref MAX_THREADS: usize = num_cpus::get();
 }
 
-#[derive(Debug, StructOpt)]
-#[structopt(name = "rusty_jetpack")]
+/// Prefix of the environment variables options are read from with --config-env
+const ENV_PREFIX: &str = "RUSTY_JETPACK_";
+
+/// How many of the slowest files to list with --verbose
+const NUM_SLOWEST_FILES: usize = 5;
+
+/// How long to wait for more changes after a file changes with --watch
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Whether Ctrl-C stops the run instead of exiting straight away
+static STOP_ON_INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+/// Whether the run was stopped with Ctrl-C
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Message migrated files are committed with by --git-commit
+const DEFAULT_COMMIT_MESSAGE: &str = "chore: migrate support library to AndroidX";
+
+#[derive(Debug, Parser)]
+#[command(name = "rusty_jetpack", version)]
 /// A fast and simple tool to assist in migrating to AndroidX.
 ///
 /// rusty_jetpack only seeks to replace all occurrences of support library classes with their
@@ -31,31 +82,1374 @@ lazy_static! {
 /// Class mapping information: https://developer.android.com/jetpack/androidx/migrate#class_mappings
 struct Opt {
     /// Silences all output to stdout
-    #[structopt(short = "q", long = "quiet")]
+    #[arg(short, long)]
     quiet: bool,
 
+    /// Prints every replaced line before and after it was migrated
+    #[arg(short, long, conflicts_with = "quiet")]
+    verbose: bool,
+
     /// Max number of threads to execute with
-    #[structopt(long = "threads")]
+    #[arg(long)]
     threads: Option<usize>,
+
+    /// Number of threads to execute with per CPU core, e.g. 0.5 to leave half of the cores for
+    /// other work or 1.5 to overlap reading files with migrating them
+    #[arg(long, value_name = "F", conflicts_with = "threads", value_parser = parse_threads_per_core)]
+    threads_per_core: Option<f64>,
+
+    /// How files are spread across the threads [default: round-robin]
+    #[arg(long, value_enum, value_name = "MODE")]
+    load_balance: Option<LoadBalance>,
+
+    /// Skip files larger than the given number of bytes
+    #[arg(long)]
+    max_file_size: Option<u64>,
+
+    /// How many directories deep to look for build files declaring artifacts [default: 2]
+    #[arg(long, value_name = "N")]
+    artifact_depth: Option<usize>,
+
+    /// Don't look for old build artifacts at all
+    #[arg(long, conflicts_with_all = ["artifact_depth", "artifact_check_all", "only_artifacts"])]
+    no_artifact_check: bool,
+
+    /// Look for old build artifacts in build files at any depth
+    #[arg(long, conflicts_with = "artifact_depth")]
+    artifact_check_all: bool,
+
+    /// Always color warnings, even when stderr isn't a terminal
+    #[arg(long, conflicts_with = "no_color")]
+    color: bool,
+
+    /// Never color warnings. Setting the NO_COLOR environment variable has the same effect
+    #[arg(long)]
+    no_color: bool,
+
+    /// Also migrate untracked files that aren't ignored by git
+    #[arg(long)]
+    untracked: bool,
+
+    /// Also migrate files in git submodules
+    #[arg(long)]
+    submodule: bool,
+
+    /// Only migrate files under this directory, e.g. a single module of a monorepo. Build files are
+    /// looked for relative to it as well
+    #[arg(long, value_name = "PATH")]
+    subdir: Option<PathBuf>,
+
+    /// Only migrate files with changes staged in git, e.g. from a pre-commit hook with --check
+    #[arg(long)]
+    staged: bool,
+
+    /// Temporarily expand a sparse checkout to migrate the files outside of it as well
+    #[arg(long)]
+    include_sparse: bool,
+
+    /// Only migrate files matching the globs listed in a .jpmigrate file instead of asking git
+    #[arg(long)]
+    use_jpmigrate: bool,
+
+    /// Skip files that haven't changed since they were last fully migrated with the same mappings,
+    /// tracked in a .rusty_jetpack_state file
+    #[arg(long, visible_alias = "skip-unchanged-files")]
+    since_last_run: bool,
+
+    /// Comma separated list of extra file extensions to migrate, e.g. "properties,txt"
+    #[arg(long, value_delimiter = ',')]
+    extensions: Vec<String>,
+
+    /// Leave lines matching this regex untouched, e.g. comments that mention the old API on
+    /// purpose. Can be given more than once to skip lines matching any of them
+    #[arg(long, value_name = "REGEX")]
+    exclude_pattern: Vec<Regex>,
+
+    /// Skip checking lines shorter than this against the mappings, instead of the length of the
+    /// shortest mapping pattern. Raising it can speed up projects with many short lines that
+    /// mention an old package
+    #[arg(long, value_name = "N")]
+    min_pattern_length: Option<usize>,
+
+    /// Warn about lines that take longer than this many milliseconds to match against the
+    /// mappings, e.g. because of a slow pattern in --mappings-dir. Matches can't be stopped part
+    /// way through, so slow lines are still migrated
+    #[arg(long, value_name = "MS")]
+    regex_timeout: Option<u64>,
+
+    /// Show the changes for each file and ask whether to apply them before writing anything
+    #[arg(short, long, conflicts_with_all = ["dry_run", "check"])]
+    interactive: bool,
+
+    /// Find everything that would be migrated without writing any changes to disk
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Exit with a non-zero status if any files would be migrated. Implies --dry-run
+    #[arg(long)]
+    check: bool,
+
+    /// Exit with a non-zero status if any star imports or old artifacts are found, since they have
+    /// to be migrated by hand
+    #[arg(long)]
+    treat_warnings_as_errors: bool,
+
+    /// Only print the path of each file that would be migrated, one per line, e.g. for piping to
+    /// xargs. Implies --dry-run and --quiet
+    #[arg(long, conflicts_with_all = ["verbose", "interactive"])]
+    only_files: bool,
+
+    /// Also list files that only declare old artifacts with --only-files
+    #[arg(long, requires = "only_files")]
+    include_artifact_files: bool,
+
+    /// Print warnings about star imports and artifacts in a format editors can parse
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    diagnostic_format: Option<DiagnosticFormat>,
+
+    /// Format to print the results in. With csv or json, nothing else is printed to stdout
+    /// [default: text]
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["only_files", "interactive"])]
+    output_format: Option<OutputFormat>,
+
+    /// Format to report old artifacts in. With json or toml, every artifact found is printed to
+    /// stdout once finished instead of a warning per build file [default: text]
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["only_files", "output_format"])]
+    artifact_report_format: Option<ArtifactReportFormat>,
+
+    /// Once finished, print the version of every AndroidX artifact group needed to include the
+    /// classes the old ones were replaced with
+    #[arg(long, conflicts_with_all = ["only_files", "output_format", "artifact_report_format"])]
+    suggest_bom_version: bool,
+
+    /// Print each unique star import once at the end instead of per file
+    #[arg(long)]
+    summarize_star_imports: bool,
+
+    /// Don't print errors for files that couldn't be migrated, only report how many there were
+    #[arg(long)]
+    ignore_errors: bool,
+
+    /// Stop migrating any more files as soon as one can't be migrated, exiting with status 2
+    #[arg(long, conflicts_with = "ignore_errors")]
+    abort_on_error: bool,
+
+    /// Stage all migrated files with `git add` once finished
+    #[arg(long)]
+    git_stage: bool,
+
+    /// Stage every change to tracked files with `git add -u` and commit them once finished
+    #[arg(long)]
+    git_commit: bool,
+
+    /// Like --git-commit, but amend the last commit instead of making a new one. Keeps the message
+    /// of the last commit unless --commit-message is given
+    #[arg(long)]
+    git_commit_amend: bool,
+
+    /// Message to commit the migrated files with [default: "chore: migrate support library to
+    /// AndroidX"]
+    #[arg(long, value_name = "MESSAGE")]
+    commit_message: Option<String>,
+
+    /// Only report old build artifacts without migrating any source files
+    #[arg(long)]
+    only_artifacts: bool,
+
+    /// Read files line by line instead of memory mapping them, for filesystems where memory
+    /// mapping is unreliable
+    #[arg(long)]
+    no_mmap: bool,
+
+    /// Read files in batches through io_uring instead of memory mapping them one at a time. Only
+    /// available on Linux when built with the io-uring feature
+    #[arg(long)]
+    io_uring: bool,
+
+    /// Encoding of the source files, e.g. "latin-1" or "windows-1252" [default: utf-8]
+    #[arg(long, value_parser = config::parse_encoding)]
+    encoding: Option<&'static Encoding>,
+
+    /// Print how many files each thread processed and how many replacements it made
+    #[arg(long)]
+    thread_stats: bool,
+
+    /// Print how many times each mapping was applied once finished
+    #[arg(long)]
+    profile: bool,
+
+    /// Keep the original modification time of migrated files, e.g. for incremental build tools
+    #[arg(long)]
+    preserve_timestamps: bool,
+
+    /// Stop writing changes once this many replacements have been made in total, as a safety net
+    /// against a bad mapping rewriting the whole project
+    #[arg(long)]
+    max_replacements: Option<usize>,
+
+    /// Leave files with more than this many replacements untouched, they're likely generated
+    #[arg(long, value_name = "N")]
+    replacement_count_threshold: Option<usize>,
+
+    /// Skip symlinked files instead of replacing the symlink with the migrated file
+    #[arg(long)]
+    no_follow_symlinks: bool,
+
+    /// Directory to load the mapping CSVs from instead of the ones built in. Any CSV that isn't in
+    /// the directory still uses the built in version
+    #[arg(long)]
+    mappings_dir: Option<PathBuf>,
+
+    /// Scan every file this many times without writing anything and print how long the runs took,
+    /// e.g. to profile changes to the matching
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["interactive", "watch"])]
+    benchmark: Option<u32>,
+
+    /// Print every loaded mapping pattern and its replacement, in the order they're checked, and
+    /// exit
+    #[arg(long)]
+    show_patterns: bool,
+
+    /// Print a completion script for the given shell and exit
+    #[arg(long, value_name = "SHELL")]
+    generate_completion: Option<Shell>,
+
+    /// Write migrated files to the same relative path in this directory, leaving the originals
+    /// untouched
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// Write changes straight into the original files instead of writing a temp file and renaming
+    /// it over them. Faster on filesystems where renaming is slow, but a crash part way through
+    /// can leave a file truncated
+    #[arg(long)]
+    no_tempfile: bool,
+
+    /// Write a Markdown log of every migrated file to this path once finished, e.g. for a PR
+    /// description
+    #[arg(long, value_name = "PATH")]
+    changelog: Option<PathBuf>,
+
+    /// Keep running after the initial migration and migrate files again as soon as they change,
+    /// until stopped with Ctrl-C
+    #[arg(long, conflicts_with_all = ["check", "interactive"])]
+    watch: bool,
+
+    /// Wait for every file to finish and print the results sorted by path, so the output is the
+    /// same from run to run
+    #[arg(long)]
+    sort_output: bool,
+
+    /// TOML file to read default options from [default: .rusty_jetpack.toml]
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Also read options that weren't given on the command line from environment variables,
+    /// before falling back to the config file. Supported variables are RUSTY_JETPACK_THREADS,
+    /// RUSTY_JETPACK_MAX_FILE_SIZE, RUSTY_JETPACK_MAX_REPLACEMENTS, RUSTY_JETPACK_MAPPINGS_DIR,
+    /// RUSTY_JETPACK_OUTPUT_DIR and the flags RUSTY_JETPACK_QUIET, RUSTY_JETPACK_VERBOSE,
+    /// RUSTY_JETPACK_DRY_RUN, RUSTY_JETPACK_CHECK, RUSTY_JETPACK_UNTRACKED and
+    /// RUSTY_JETPACK_SUBMODULE, which are set by "1" or "true"
+    #[arg(long)]
+    config_env: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Check the mapping CSVs for duplicate, unreachable or invalid patterns and replacements
+    /// that are still old packages
+    ValidateMappings,
+    /// Show every mapping that matches a line and which one would be applied to it
+    Explain {
+        /// The line of source code to explain
+        line: String,
+    },
+}
+
+impl Opt {
+    /// Fill in any options that weren't given on the command line from the environment.
+    ///
+    /// * overridden - Whether an option is overridden on the command line, given its long name
+    ///
+    /// Returns the long names of the options that were set from the environment
+    fn merge_env(&mut self, overridden: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut set = Vec::new();
+        let mut flag = |name: &str| {
+            let option = env_option_name(name);
+            let on = !overridden(&option) && env_flag(name);
+            if on {
+                set.push(option);
+            }
+            on
+        };
+        self.quiet |= flag("QUIET");
+        self.verbose |= flag("VERBOSE");
+        self.dry_run |= flag("DRY_RUN");
+        self.check |= flag("CHECK");
+        self.untracked |= flag("UNTRACKED");
+        self.submodule |= flag("SUBMODULE");
+        merge_env_value("THREADS", &mut self.threads, &overridden, &mut set);
+        merge_env_value(
+            "MAX_FILE_SIZE",
+            &mut self.max_file_size,
+            &overridden,
+            &mut set,
+        );
+        merge_env_value(
+            "MAX_REPLACEMENTS",
+            &mut self.max_replacements,
+            &overridden,
+            &mut set,
+        );
+        merge_env_value(
+            "MAPPINGS_DIR",
+            &mut self.mappings_dir,
+            &overridden,
+            &mut set,
+        );
+        merge_env_value("OUTPUT_DIR", &mut self.output_dir, &overridden, &mut set);
+        set
+    }
+
+    /// Fill in any options that weren't given on the command line from the config file. Options
+    /// overridden on the command line are already left out of the config.
+    ///
+    /// * config - The config to read defaults from
+    fn merge_config(&mut self, config: Config) {
+        if !self.quiet && !self.verbose {
+            self.quiet = config.quiet.unwrap_or_default();
+            self.verbose = config.verbose.unwrap_or_default();
+        }
+        if self.threads.is_none() && self.threads_per_core.is_none() {
+            self.threads = config.threads;
+            self.threads_per_core = config.threads_per_core;
+        }
+        self.max_file_size = self.max_file_size.or(config.max_file_size);
+        self.load_balance = self.load_balance.or(config.load_balance);
+        self.artifact_depth = self.artifact_depth.or(config.artifact_depth);
+        self.no_artifact_check |= config.no_artifact_check.unwrap_or_default();
+        self.artifact_check_all |= config.artifact_check_all.unwrap_or_default();
+        if !self.color && !self.no_color {
+            self.color = config.color.unwrap_or_default();
+            self.no_color = config.no_color.unwrap_or_default();
+        }
+        self.untracked |= config.untracked.unwrap_or_default();
+        if self.extensions.is_empty() {
+            self.extensions = config.extensions.unwrap_or_default();
+        }
+        if self.exclude_pattern.is_empty() {
+            self.exclude_pattern = config.exclude_pattern.unwrap_or_default();
+        }
+        self.min_pattern_length = self.min_pattern_length.or(config.min_pattern_length);
+        self.regex_timeout = self.regex_timeout.or(config.regex_timeout);
+        self.dry_run |= config.dry_run.unwrap_or_default();
+        self.check |= config.check.unwrap_or_default();
+        self.treat_warnings_as_errors |= config.treat_warnings_as_errors.unwrap_or_default();
+        self.only_files |= config.only_files.unwrap_or_default();
+        self.include_artifact_files |= config.include_artifact_files.unwrap_or_default();
+        self.ignore_errors |= config.ignore_errors.unwrap_or_default();
+        self.abort_on_error |= config.abort_on_error.unwrap_or_default();
+        self.summarize_star_imports |= config.summarize_star_imports.unwrap_or_default();
+        self.git_stage |= config.git_stage.unwrap_or_default();
+        self.git_commit |= config.git_commit.unwrap_or_default();
+        self.git_commit_amend |= config.git_commit_amend.unwrap_or_default();
+        self.commit_message = self.commit_message.take().or(config.commit_message);
+        self.no_follow_symlinks |= config.no_follow_symlinks.unwrap_or_default();
+        self.only_artifacts |= config.only_artifacts.unwrap_or_default();
+        self.io_uring |= config.io_uring.unwrap_or_default();
+        self.encoding = self.encoding.or(config.encoding);
+        self.thread_stats |= config.thread_stats.unwrap_or_default();
+        self.profile |= config.profile.unwrap_or_default();
+        self.preserve_timestamps |= config.preserve_timestamps.unwrap_or_default();
+        self.max_replacements = self.max_replacements.or(config.max_replacements);
+        self.replacement_count_threshold = self
+            .replacement_count_threshold
+            .or(config.replacement_count_threshold);
+        self.interactive |= config.interactive.unwrap_or_default();
+        self.submodule |= config.submodule.unwrap_or_default();
+        self.staged |= config.staged.unwrap_or_default();
+        self.subdir = self.subdir.take().or(config.subdir);
+        self.include_sparse |= config.include_sparse.unwrap_or_default();
+        self.use_jpmigrate |= config.use_jpmigrate.unwrap_or_default();
+        self.since_last_run |= config.since_last_run.unwrap_or_default();
+        self.no_mmap |= config.no_mmap.unwrap_or_default();
+        self.diagnostic_format = self.diagnostic_format.or(config.diagnostic_format);
+        self.output_format = self.output_format.or(config.output_format);
+        self.artifact_report_format = self
+            .artifact_report_format
+            .or(config.artifact_report_format);
+        self.suggest_bom_version |= config.suggest_bom_version.unwrap_or_default();
+        self.mappings_dir = self.mappings_dir.take().or(config.mappings_dir);
+        self.output_dir = self.output_dir.take().or(config.output_dir);
+        self.no_tempfile |= config.no_tempfile.unwrap_or_default();
+        self.changelog = self.changelog.take().or(config.changelog);
+        self.watch |= config.watch.unwrap_or_default();
+        self.sort_output |= config.sort_output.unwrap_or_default();
+    }
+
+    /// The number of threads to execute with. Only --threads-per-core can go over the number of
+    /// CPU cores.
+    fn num_threads(&self) -> usize {
+        match self.threads_per_core {
+            Some(per_core) => max(1, (*MAX_THREADS as f64 * per_core).round() as usize),
+            None => min(self.threads.unwrap_or(*MAX_THREADS), *MAX_THREADS),
+        }
+    }
+
+    /// Whether files should be left untouched on disk
+    fn dry_run(&self) -> bool {
+        self.dry_run || self.check || self.only_files
+    }
+
+    /// Whether extra debugging information should be printed, either with --verbose or by
+    /// setting RUST_LOG=debug
+    fn debug(&self) -> bool {
+        self.verbose
+            || env::var("RUST_LOG").is_ok_and(|level| {
+                level.eq_ignore_ascii_case("debug") || level.eq_ignore_ascii_case("trace")
+            })
+    }
+}
+
+/// Stats collected over the course of a run
+struct Summary {
+    num_changes: usize,
+    changed_files: Vec<PathBuf>,
+    /// Whether any changes weren't written because of --max-replacements
+    exceeded_max_replacements: bool,
+    /// Whether any star imports or artifacts were found
+    found_warnings: bool,
+    /// Files that would be changed along with their replaced lines, only kept with --interactive
+    proposed_changes: Vec<(PathBuf, Vec<ReplacedLine>)>,
+    /// Files with nothing left to migrate, only kept with --since-last-run
+    migrated_files: Vec<PathBuf>,
+    /// What was found in each file, only kept with --changelog
+    changelog: Vec<ChangelogEntry>,
+    /// Every file with anything to migrate, only kept with --output-format json
+    files: Vec<FileSummary>,
+    /// Every old artifact found mapped to its replacement, only kept with --artifact-report-format
+    artifacts: BTreeMap<String, String>,
+    /// Every class the old ones were replaced with, only kept with --suggest-bom-version
+    replacement_classes: BTreeSet<String>,
 }
 
 fn main() {
     let start = Instant::now();
+    let started_at = SystemTime::now();
+
+    // Parse the cli options, fill in the rest from the config, and start execution
+    let mut command = Opt::command();
+    let matches = command.get_matches_mut();
+    let mut opts = Opt::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(shell) = opts.generate_completion {
+        clap_complete::generate(
+            shell,
+            &mut Opt::command(),
+            "rusty_jetpack",
+            &mut io::stdout(),
+        );
+        return;
+    }
+    let mut env_options = Vec::new();
+    if opts.config_env {
+        env_options = opts.merge_env(|name| overridden(&command, &matches, &[], name));
+        check_conflicts(&mut command, &env_options, "environment");
+    }
+    match Config::load(opts.config.as_deref(), |name| {
+        overridden(&command, &matches, &env_options, name)
+    }) {
+        Ok(config) => {
+            check_conflicts(&mut command, &config.options, "config");
+            opts.merge_config(config);
+        }
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
+            process::exit(1);
+        }
+    }
+    // Nothing but the paths, CSV, JSON or the artifact report can go to stdout
+    let machine_readable = matches!(
+        opts.output_format,
+        Some(OutputFormat::Csv | OutputFormat::Json)
+    ) || matches!(
+        opts.artifact_report_format,
+        Some(ArtifactReportFormat::Json | ArtifactReportFormat::Toml)
+    );
+    if opts.only_files || machine_readable {
+        opts.quiet = true;
+        opts.verbose = false;
+    }
+    if let Some(dir) = &opts.mappings_dir {
+        if !dir.is_dir() {
+            eprintln!(
+                "Mappings directory {} does not exist",
+                dir.to_string_lossy()
+            );
+            process::exit(1);
+        }
+        mappings::set_mappings_dir(dir.clone());
+    }
+
+    if let Some(dir) = &opts.subdir {
+        if !dir.is_dir() {
+            eprintln!("Subdirectory {} does not exist", dir.to_string_lossy());
+            process::exit(1);
+        }
+        // Listed paths never start with "./", so leave it out to be able to compare against them
+        opts.subdir = Some(
+            dir.components()
+                .filter(|c| !matches!(c, Component::CurDir))
+                .collect(),
+        );
+    }
+
+    if opts.show_patterns {
+        show_patterns();
+        return;
+    }
+
+    if opts.use_jpmigrate && !Path::new(finder::JPMIGRATE_FILE).is_file() {
+        eprintln!("No {} file found", finder::JPMIGRATE_FILE);
+        process::exit(1);
+    }
+
+    if opts.io_uring && !cfg!(all(target_os = "linux", feature = "io-uring")) && !opts.quiet {
+        eprintln!("Built without io_uring support, memory mapping files instead");
+    }
+
+    match &opts.command {
+        Some(Commands::ValidateMappings) => process::exit(validate_mappings()),
+        Some(Commands::Explain { line }) => process::exit(explain(&opts, line)),
+        None => {}
+    }
+
+    if let Some(runs) = opts.benchmark {
+        // Nothing is written so every run scans exactly the same files
+        opts.dry_run = true;
+        opts.quiet = true;
+        benchmark(&opts, runs);
+        return;
+    }
+
+    let last_run = opts
+        .since_last_run
+        .then(|| State::load(Path::new(state::STATE_FILE)).for_mappings(state_key(&opts)));
+    // Files outside of a sparse checkout aren't on disk, so they can only be migrated by expanding
+    // it for the duration of the run
+    let mut sparse_checkout = None;
+    if !opts.use_jpmigrate && finder::is_sparse_checkout(Path::new(".")) {
+        if opts.include_sparse {
+            match SparseCheckout::expand() {
+                Ok(expanded) => sparse_checkout = Some(expanded),
+                Err(e) => {
+                    eprintln!("Failed to expand the sparse checkout: {}", e);
+                    process::exit(1);
+                }
+            }
+        } else if !opts.quiet {
+            eprintln!(
+                "Sparse checkout detected, files outside of it won't be migrated unless \
+                 --include-sparse is passed"
+            );
+        }
+    }
+
+    let (rx_finder, rx_matcher, aborted) = start_execution(&opts, last_run.clone());
+    handle_interrupts(&aborted);
+    let mut summary = listen_for_messages(start, &opts, rx_finder, rx_matcher, &aborted);
+    // Ctrl-C has to stop the prompts with --interactive and the watching with --watch right away
+    restore_interrupts();
+
+    if opts.output_format == Some(OutputFormat::Json) {
+        let report = JsonReport::new(started_at, std::mem::take(&mut summary.files));
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    }
+    if let Some(format) = opts.artifact_report_format {
+        if let Some(report) = report::format_artifact_report(format, &summary.artifacts) {
+            print!("{}", report);
+        }
+    }
+
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        eprintln!("Interrupted, some files were not migrated");
+        exit_restoring_sparse_checkout(sparse_checkout, 130);
+    }
+    if aborted.load(Ordering::SeqCst) {
+        eprintln!("Aborted after the first error, some files were not migrated");
+        exit_restoring_sparse_checkout(sparse_checkout, 2);
+    }
+
+    if opts.suggest_bom_version {
+        print_bom_suggestion(&summary.replacement_classes);
+    }
+
+    if opts.interactive && !summary.proposed_changes.is_empty() {
+        summary.changed_files = apply_confirmed_changes(&opts, &summary.proposed_changes);
+        // Changes that weren't confirmed didn't happen
+        for entry in &mut summary.changelog {
+            if !summary.changed_files.contains(&entry.path) {
+                entry.replacements = 0;
+            }
+        }
+    }
+
+    if let Some(path) = &opts.changelog {
+        if let Err(e) = changelog::write_changelog(path, &summary.changelog) {
+            eprintln!("Failed to write {}: {}", path.to_string_lossy(), e);
+            exit_restoring_sparse_checkout(sparse_checkout, 1);
+        }
+    }
+
+    if let Some(sparse_checkout) = sparse_checkout {
+        if let Err(e) = sparse_checkout.restore() {
+            eprintln!("Failed to restore the sparse checkout: {}", e);
+            process::exit(1);
+        }
+    }
+
+    if summary.exceeded_max_replacements {
+        process::exit(1);
+    }
+
+    // Fail in check mode so CI can enforce that nothing is left to migrate
+    if opts.check && summary.num_changes > 0 {
+        process::exit(1);
+    }
+
+    // Star imports and artifacts can't be migrated automatically, so strict runs fail on them
+    if opts.treat_warnings_as_errors && summary.found_warnings {
+        process::exit(1);
+    }
+
+    // Only remember what was migrated once the run is known to have succeeded
+    if let Some(mut state) = last_run {
+        state.record(summary.migrated_files.iter());
+        if let Err(e) = state.save(Path::new(state::STATE_FILE)) {
+            eprintln!("Failed to save {}: {}", state::STATE_FILE, e);
+            process::exit(1);
+        }
+    }
+
+    // Nothing in the repo changed when writing to another directory
+    if opts.git_stage
+        && !opts.dry_run()
+        && opts.output_dir.is_none()
+        && !summary.changed_files.is_empty()
+    {
+        if let Err(e) = git_stage(&summary.changed_files, opts.submodule, opts.include_sparse) {
+            eprintln!(
+                "Failed to stage migrated files, changes are left on disk: {}",
+                e
+            );
+            process::exit(1);
+        }
+    }
+
+    if (opts.git_commit || opts.git_commit_amend)
+        && !opts.dry_run()
+        && opts.output_dir.is_none()
+        && !summary.changed_files.is_empty()
+    {
+        // Only fall back to the default message when there's no previous one to keep
+        let message = match &opts.commit_message {
+            Some(message) => Some(message.as_str()),
+            None if opts.git_commit_amend => None,
+            None => Some(DEFAULT_COMMIT_MESSAGE),
+        };
+        if let Err(e) = git_commit(message, opts.git_commit_amend) {
+            eprintln!(
+                "Failed to commit migrated files, changes are left on disk: {}",
+                e
+            );
+            process::exit(1);
+        }
+    }
+
+    if opts.watch {
+        if let Err(e) = watch(&opts) {
+            eprintln!("Failed to watch for changes: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Exit with the given status code, restoring the sparse checkout first if it was expanded. The
+/// process exits without running any destructors, so it has to be dropped beforehand.
+///
+/// * sparse_checkout - The expanded sparse checkout, if any
+/// * code - The status code to exit with
+fn exit_restoring_sparse_checkout(sparse_checkout: Option<SparseCheckout>, code: i32) -> ! {
+    drop(sparse_checkout);
+    process::exit(code)
+}
+
+/// Parse the number of threads per CPU core, which has to be a positive number.
+///
+/// * value - The value given on the command line
+fn parse_threads_per_core(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(per_core) if per_core.is_finite() && per_core > 0.0 => Ok(per_core),
+        Ok(_) => Err(String::from("must be a positive number")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Stop the run on Ctrl-C instead of exiting straight away. No more files are started, the ones
+/// already being migrated are finished, and everything found so far is still reported. A second
+/// Ctrl-C exits straight away.
+///
+/// * aborted - The token to set to stop the finder and matchers
+fn handle_interrupts(aborted: &Arc<AtomicBool>) {
+    let aborted = aborted.clone();
+    STOP_ON_INTERRUPT.store(true, Ordering::SeqCst);
+    let result = ctrlc::set_handler(move || {
+        if !STOP_ON_INTERRUPT.load(Ordering::SeqCst) || INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+        aborted.store(true, Ordering::SeqCst);
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to handle Ctrl-C, it will exit straight away: {}", e);
+    }
+}
+
+/// Go back to exiting straight away on Ctrl-C.
+fn restore_interrupts() {
+    STOP_ON_INTERRUPT.store(false, Ordering::SeqCst);
+}
+
+/// The key the --since-last-run state is kept under. Besides the mappings it covers every option
+/// that leaves part of a file unchecked, since files that were skipped that way aren't fully
+/// migrated and have to be checked again without them.
+///
+/// * opts - The options of the run
+fn state_key(opts: &Opt) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(mappings::mappings_hash());
+    hasher.update(format!(
+        "{}:{}:{}:{:?}:{:?}",
+        opts.only_artifacts,
+        opts.no_artifact_check,
+        opts.artifact_check_all,
+        opts.artifact_depth,
+        opts.min_pattern_length
+    ));
+    for pattern in &opts.exclude_pattern {
+        hasher.update(pattern.as_str());
+        // Keep patterns apart so e.g. ["ab", "c"] and ["a", "bc"] don't hash the same
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Whether an option is overridden, either because it was given itself or because an option it
+/// conflicts with was. Options from the environment are overridden by the command line, and
+/// options from the config by both.
+///
+/// * command - The cli definition
+/// * matches - The parsed cli options
+/// * env_options - The long names of the options set from the environment
+/// * name - The long name of the option
+fn overridden(
+    command: &clap::Command,
+    matches: &ArgMatches,
+    env_options: &[String],
+    name: &str,
+) -> bool {
+    let arg = match find_arg(command, name) {
+        Some(arg) => arg,
+        None => return false,
+    };
+    std::iter::once(arg)
+        .chain(conflicting_args(command, arg))
+        .any(|arg| {
+            matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+                || arg
+                    .get_long()
+                    .is_some_and(|long| env_options.iter().any(|option| option == long))
+        })
+}
+
+/// Exit with the same error clap gives for conflicting options if the config or environment sets
+/// any options that conflict with each other.
+///
+/// * command - The cli definition
+/// * options - The long names of the options that were set
+/// * source - Where the options were set, e.g. "config"
+fn check_conflicts(command: &mut clap::Command, options: &[String], source: &str) {
+    let conflict = options.iter().find_map(|name| {
+        let arg = find_arg(command, name)?;
+        conflicting_args(command, arg)
+            .into_iter()
+            .filter_map(|other| other.get_long())
+            .find(|other| options.iter().any(|option| option == other))
+            .map(|other| (name.clone(), other.to_string()))
+    });
+    if let Some((name, other)) = conflict {
+        command
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "the argument '--{}' cannot be used with '--{}' in the {}",
+                    name, other, source
+                ),
+            )
+            .exit();
+    }
+}
+
+/// Find a cli option by its long name.
+///
+/// * command - The cli definition
+/// * name - The long name of the option without the dashes
+fn find_arg<'a>(command: &'a clap::Command, name: &str) -> Option<&'a Arg> {
+    command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(name))
+}
+
+/// Every cli option that can't be used together with the given one, whichever of the two declares
+/// the conflict.
+///
+/// * command - The cli definition
+/// * arg - The option to find the conflicts of
+fn conflicting_args<'a>(command: &'a clap::Command, arg: &Arg) -> Vec<&'a Arg> {
+    let id = arg.get_id();
+    let declared: Vec<_> = command
+        .get_arg_conflicts_with(arg)
+        .into_iter()
+        .map(Arg::get_id)
+        .collect();
+    command
+        .get_arguments()
+        .filter(|other| {
+            declared.contains(&other.get_id())
+                || command
+                    .get_arg_conflicts_with(other)
+                    .iter()
+                    .any(|conflict| conflict.get_id() == id)
+        })
+        .collect()
+}
+
+/// Read an option from its environment variable unless it's overridden on the command line.
+/// Values that can't be parsed are ignored with a warning.
+///
+/// * name - The name of the variable without the RUSTY_JETPACK_ prefix, e.g. "THREADS"
+/// * value - The option to fill in
+/// * overridden - Whether an option is overridden on the command line, given its long name
+/// * set - The long names of the options set from the environment so far
+fn merge_env_value<T: FromStr>(
+    name: &str,
+    value: &mut Option<T>,
+    overridden: impl Fn(&str) -> bool,
+    set: &mut Vec<String>,
+) {
+    let option = env_option_name(name);
+    if value.is_some() || overridden(&option) {
+        return;
+    }
+    let var = format!("{}{}", ENV_PREFIX, name);
+    let raw = match env::var(&var) {
+        Ok(raw) => raw,
+        Err(_) => return,
+    };
+    match raw.parse() {
+        Ok(parsed) => {
+            *value = Some(parsed);
+            set.push(option);
+        }
+        Err(_) => eprintln!("Ignoring {} since \"{}\" isn't a valid value", var, raw),
+    }
+}
+
+/// The long name of the option an environment variable sets, e.g. "max-file-size" for
+/// "MAX_FILE_SIZE".
+///
+/// * name - The name of the variable without the RUSTY_JETPACK_ prefix
+fn env_option_name(name: &str) -> String {
+    name.to_lowercase().replace('_', "-")
+}
+
+/// Whether a flag is set by its environment variable.
+///
+/// * name - The name of the variable without the RUSTY_JETPACK_ prefix, e.g. "QUIET"
+fn env_flag(name: &str) -> bool {
+    env::var(format!("{}{}", ENV_PREFIX, name))
+        .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+/// Build the options for the matchers from the cli options.
+///
+/// * `opts` - The options to build from
+fn matcher_options(opts: &Opt) -> MatcherOptions {
+    MatcherOptions {
+        max_file_size: opts.max_file_size,
+        dry_run: opts.dry_run() || opts.interactive,
+        debug: opts.debug(),
+        no_follow_symlinks: opts.no_follow_symlinks,
+        only_artifacts: opts.only_artifacts,
+        io_uring: opts.io_uring,
+        encoding: opts.encoding,
+        profile: opts.profile,
+        preserve_timestamps: opts.preserve_timestamps,
+        no_mmap: opts.no_mmap,
+        max_replacements: opts.max_replacements,
+        total_replacements: Default::default(),
+        no_tempfile: opts.no_tempfile,
+        output_dir: opts.output_dir.clone(),
+        aborted: Default::default(),
+        replacement_count_threshold: opts.replacement_count_threshold,
+        artifact_depth: opts.artifact_depth,
+        no_artifact_check: opts.no_artifact_check,
+        artifact_check_all: opts.artifact_check_all,
+        subdir: opts.subdir.clone(),
+        // Every pattern was already checked when parsing them, so they can't fail to compile
+        exclude_patterns: RegexSet::new(opts.exclude_pattern.iter().map(Regex::as_str)).unwrap(),
+        min_pattern_length: opts.min_pattern_length,
+        suggest_bom_version: opts.suggest_bom_version,
+        regex_timeout: opts.regex_timeout.map(Duration::from_millis),
+    }
+}
+
+/// Print the version of every AndroidX artifact group needed to include the classes the old ones
+/// were replaced with, along with any classes that aren't in a known group.
+///
+/// * classes - Every class the old ones were replaced with
+fn print_bom_suggestion(classes: &BTreeSet<String>) {
+    let suggestion = bom::suggest_bom_version(classes);
+    if suggestion.versions.is_empty() && suggestion.unknown.is_empty() {
+        println!("No AndroidX classes were introduced, no versions are needed");
+    } else if !suggestion.versions.is_empty() {
+        println!("Minimum AndroidX versions:");
+        for (group, version) in &suggestion.versions {
+            println!("  {} {}", group, version);
+        }
+    }
+    if !suggestion.unknown.is_empty() {
+        eprintln!(
+            "{} AndroidX class(es) aren't in any known artifact group:",
+            suggestion.unknown.len()
+        );
+        for class in &suggestion.unknown {
+            eprintln!("  * {}", class);
+        }
+    }
+}
+
+/// Ask whether to apply the changes to each file, then write out the ones that were confirmed.
+/// Confirmed files are migrated again from scratch on this thread since someone is waiting on the
+/// prompt anyways.
+///
+/// * `opts` - The options to migrate files with
+/// * `proposed` - Each file that would be changed along with its replaced lines
+///
+/// Returns the paths of the files that were changed
+fn apply_confirmed_changes(opts: &Opt, proposed: &[(PathBuf, Vec<ReplacedLine>)]) -> Vec<PathBuf> {
+    let confirmed = match interactive::confirm_changes(io::stdin().lock(), io::stdout(), proposed) {
+        Ok(confirmed) => confirmed,
+        Err(e) => {
+            eprintln!("Failed to confirm changes, nothing was written: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let (tx_paths, rx_paths) = unbounded();
+    confirmed
+        .into_iter()
+        .for_each(|path| tx_paths.send(path).unwrap());
+    drop(tx_paths);
+
+    let (tx, rx) = unbounded();
+    let options = MatcherOptions {
+        dry_run: false,
+        ..matcher_options(opts)
+    };
+    Matcher::new(0, tx, options).run(rx_paths);
+
+    let mut num_changes = 0;
+    let mut changed_files = Vec::new();
+    for result in rx.iter() {
+        match result {
+            Ok(match_info) if match_info.matches_found > 0 => {
+                num_changes += match_info.matches_found;
+                changed_files.push(match_info.path);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if !opts.quiet {
+        println!(
+            "Replaced {} occurrence(s) in {} file(s)!",
+            num_changes,
+            changed_files.len()
+        );
+    }
+    changed_files
+}
+
+/// Watch the project for changes and migrate each changed file as soon as it's saved, printing
+/// the result for any file that still needed attention. Only returns if watching fails.
+///
+/// * `opts` - The options to migrate files with
+fn watch(opts: &Opt) -> notify::Result<()> {
+    let (tx_events, rx_events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx_events)?;
+    watcher.watch(Path::new("."), RecursiveMode::Recursive)?;
+
+    let finder = finder::Finder::new(FinderOptions {
+        extensions: opts.extensions.clone(),
+        ..Default::default()
+    });
+    // Build outputs are constantly rewritten, so leave out anything git would ignore
+    let (gitignore, _) = Gitignore::new(".gitignore");
+    let cwd = env::current_dir()?;
+    let (tx, rx) = unbounded();
+    let matcher = Matcher::new(0, tx, matcher_options(opts));
+    if !opts.quiet {
+        println!("Watching for changes, press Ctrl-C to stop...");
+    }
+
+    while let Ok(event) = rx_events.recv() {
+        // Editors often save a file in several steps, so wait for them to finish before reading it
+        let mut paths = BTreeSet::new();
+        let mut event = Some(event);
+        while let Some(next) = event {
+            match next {
+                Ok(next) if matches!(next.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    paths.extend(next.paths.into_iter().map(|path| {
+                        let relative = path.strip_prefix(&cwd).or_else(|_| path.strip_prefix("."));
+                        relative.map(Path::to_path_buf).unwrap_or(path)
+                    }))
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to watch for changes: {}", e),
+            }
+            event = rx_events.recv_timeout(WATCH_DEBOUNCE).ok();
+        }
+
+        paths
+            .into_iter()
+            .filter(|path| {
+                path.is_file()
+                    && finder.has_migratable_extension(path)
+                    && opts.subdir.as_ref().is_none_or(|dir| path.starts_with(dir))
+                    && !path.starts_with(".git")
+                    && !gitignore
+                        .matched_path_or_any_parents(path, false)
+                        .is_ignore()
+            })
+            .for_each(|path| matcher.process(path));
+
+        // Files saved by this tool show up again with nothing left to do, so only report the ones
+        // that still needed something
+        let (tx_results, rx_results) = unbounded();
+        rx.try_iter()
+            .filter(|result| match result {
+                Ok(match_info) => {
+                    match_info.matches_found > 0
+                        || !match_info.artifacts_found.is_empty()
+                        || !match_info.matched_star_imports.is_empty()
+                        || !match_info.unrecognized_patterns.is_empty()
+                }
+                Err(_) => true,
+            })
+            .for_each(|result| tx_results.send(result).unwrap());
+        drop(tx_results);
+        if !rx_results.is_empty() {
+            listen_for_messages(
+                Instant::now(),
+                opts,
+                never(),
+                rx_results,
+                &AtomicBool::new(false),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print every loaded mapping grouped by category, longest pattern first the same as they're
+/// checked.
+fn show_patterns() {
+    let categories = [
+        (Category::Support, &*mappings::SUPPORT_MAPPINGS),
+        (Category::Arch, &*mappings::ARCH_MAPPINGS),
+        (Category::Databind, &*mappings::DATABIND_MAPPINGS),
+        (Category::Kotlinx, &*mappings::KOTLINX_MAPPINGS),
+    ];
+    for (category, mappings) in categories {
+        println!("{} ({} mappings):", category, mappings.len());
+        for mapping in mappings.iter() {
+            println!("  {} => {}", mapping.pattern, mapping.replacement);
+        }
+    }
+
+    println!("artifact ({} mappings):", mappings::ARTIFACT_MAPPINGS.len());
+    for mapping in mappings::ARTIFACT_MAPPINGS.iter() {
+        println!(
+            "  {} => {}",
+            mapping.pattern,
+            mapping.replacement_with_version()
+        );
+    }
+}
+
+/// Scan every file the given number of times and print how long the runs took along with the
+/// throughput. Nothing is written to disk.
+///
+/// * opts - The CLI options passed in
+/// * runs - How many times to scan every file
+fn benchmark(opts: &Opt, runs: u32) {
+    // Load the mappings up front so the first run isn't slower than the rest
+    lazy_static::initialize(&mappings::SUPPORT_MAPPINGS);
+
+    let mut durations = Vec::new();
+    let mut num_files = 0;
+    let mut num_bytes: u64 = 0;
+    for run in 1..=runs {
+        let start = Instant::now();
+        let (_rx_finder, rx_matcher, _) = start_execution(opts, None);
+        num_files = 0;
+        num_bytes = 0;
+        for match_info in rx_matcher.iter().flatten() {
+            if !match_info.skipped_too_large && !match_info.skipped_symlink {
+                num_files += 1;
+                num_bytes += match_info.file_size;
+            }
+        }
+        let duration = start.elapsed();
+        println!(
+            "Run {}/{}: {:.3}s, {} file(s)",
+            run,
+            runs,
+            duration.as_secs_f64(),
+            num_files
+        );
+        durations.push(duration);
+    }
+
+    durations.sort_unstable();
+    let total: Duration = durations.iter().sum();
+    let middle = durations.len() / 2;
+    let median = if durations.len() % 2 == 0 {
+        (durations[middle - 1] + durations[middle]) / 2
+    } else {
+        durations[middle]
+    };
+    let megabytes = num_bytes as f64 / 1_000_000.0;
+    println!(
+        "{} run(s) over {} file(s) and {:.2} MB each",
+        runs, num_files, megabytes
+    );
+    println!(
+        "  mean {:.3}s, median {:.3}s, min {:.3}s, max {:.3}s",
+        total.as_secs_f64() / f64::from(runs),
+        median.as_secs_f64(),
+        durations[0].as_secs_f64(),
+        durations[durations.len() - 1].as_secs_f64()
+    );
+    println!(
+        "  throughput {:.2} MB/s",
+        megabytes * f64::from(runs) / total.as_secs_f64()
+    );
+}
+
+/// Check the mapping CSVs and print any issues found.
+///
+/// Returns the exit code for the process, non-zero if any issues were found
+fn validate_mappings() -> i32 {
+    let issues = mappings::validate_mappings();
+    for issue in &issues {
+        eprintln!("{}", issue);
+    }
+
+    if issues.is_empty() {
+        println!("No issues found in the mappings");
+        0
+    } else {
+        eprintln!("Found {} issue(s) in the mappings", issues.len());
+        1
+    }
+}
+
+/// Print every mapping that matches the given line and which one would be applied.
+///
+/// * opts - The options to match with
+/// * line - The line of source code to explain
+///
+/// Returns the exit code for the process, non-zero if no mapping would be applied
+fn explain(opts: &Opt, line: &str) -> i32 {
+    let (tx, _) = unbounded();
+    let explanations = Matcher::new(0, tx, matcher_options(opts)).explain(line);
+    if explanations.is_empty() {
+        println!("No mappings match the line");
+    }
+    for explanation in &explanations {
+        println!(
+            "{:<8} {}..{}  {} => {}{}",
+            explanation.category.to_string(),
+            explanation.range.start,
+            explanation.range.end,
+            explanation.pattern,
+            explanation.replacement,
+            if explanation.applied {
+                " (applied)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    if explanations.iter().any(|explanation| explanation.applied) {
+        0
+    } else {
+        1
+    }
+}
+
+/// Stage the given files with `git add`. Files in submodules are staged from within their
+/// submodule since the superproject can't stage them.
+///
+/// * paths - The files to stage
+/// * submodules - Whether any of the files could be in a submodule
+/// * sparse - Whether any of the files could be outside of a sparse checkout
+fn git_stage(paths: &[PathBuf], submodules: bool, sparse: bool) -> io::Result<()> {
+    let submodule_paths = if submodules {
+        git_submodule_paths()?
+    } else {
+        Vec::new()
+    };
+
+    // Group the files by the repo they belong to, using the most nested submodule for each file
+    let mut repos: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for path in paths {
+        let repo = submodule_paths
+            .iter()
+            .filter(|submodule| path.starts_with(submodule))
+            .max_by_key(|submodule| submodule.as_os_str().len())
+            .map_or(Path::new(""), PathBuf::as_path);
+        repos
+            .entry(repo)
+            .or_default()
+            .push(path.strip_prefix(repo).unwrap_or(path));
+    }
+
+    for (repo, paths) in repos {
+        git_add(repo, &paths, sparse)?;
+    }
+    Ok(())
+}
+
+/// Run `git add` in the given repo. The paths are passed through stdin so there's no limit on
+/// how many files can be staged at once.
+///
+/// * repo - The path of the repo, or an empty path for the current directory
+/// * paths - The files to stage, relative to the repo
+/// * sparse - Whether any of the files could be outside of a sparse checkout
+fn git_add(repo: &Path, paths: &[&Path], sparse: bool) -> io::Result<()> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(repo)
+        .args(["add", "--pathspec-from-file=-"]);
+    if sparse {
+        command.arg("--sparse");
+    }
+    let mut child = command.arg("--").stdin(Stdio::piped()).spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        for path in paths {
+            writeln!(stdin, "{}", path.to_string_lossy())?;
+        }
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`git add` exited with {}",
+            status
+        )))
+    }
+}
+
+/// Stage every change to tracked files with `git add -u` and commit them.
+///
+/// * message - The commit message, or None to keep the message of the amended commit
+/// * amend - Whether to amend the last commit instead of making a new one
+fn git_commit(message: Option<&str>, amend: bool) -> io::Result<()> {
+    let mut args = vec!["commit", "--quiet"];
+    if amend {
+        args.push("--amend");
+    }
+    match message {
+        Some(message) => args.extend(["-m", message]),
+        None => args.push("--no-edit"),
+    }
+
+    for args in [&["add", "-u"][..], &args] {
+        let status = Command::new("git").args(args).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "`git {}` exited with {}",
+                args[0], status
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Get the paths of all submodules, including nested ones, relative to the current directory.
+fn git_submodule_paths() -> io::Result<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .args([
+            "submodule",
+            "--quiet",
+            "foreach",
+            "--recursive",
+            "echo \"$displaypath\"",
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "`git submodule foreach` exited with {}",
+            output.status
+        )));
+    }
 
-    // Parse the cli options and start execution
-    let opts = Opt::from_args();
-    let rx_matcher = start_execution(&opts);
-    listen_for_messages(start, &opts, rx_matcher);
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(PathBuf::from)
+        .collect())
 }
 
 /// Starts the execution of the matchers by creating a matcher per number of specified threads or
-/// the max number of threads available. It then spawns a finder to feed the matchers with files
-/// that can be migrated.
+/// the max number of threads available. It then spawns a finder on its own thread to feed the
+/// matchers with files that can be migrated as they are found.
 ///
 /// * opts - The CLI options passed in
-/// Returns the Receiver listening to the unbounded channel the matchers will respond on
-fn start_execution(opts: &Opt) -> Receiver<Result<MatchInfo>> {
-    let num_threads = min(opts.threads.unwrap_or(*MAX_THREADS), *MAX_THREADS);
+/// * last_run - What was migrated on the last run, with --since-last-run
+///
+/// Returns the Receiver the finder will report on once it is done, the Receiver listening to the
+/// unbounded channel the matchers will respond on, and the token to set to stop the finder and
+/// matchers early
+fn start_execution(
+    opts: &Opt,
+    last_run: Option<State>,
+) -> (
+    Receiver<FinderInfo>,
+    Receiver<Result<MatchInfo, MigrationError>>,
+    Arc<AtomicBool>,
+) {
+    let num_threads = opts.num_threads();
+    let aborted = Arc::new(AtomicBool::new(false));
 
     if !opts.quiet {
         println!("Starting with {} threads...", num_threads);
@@ -64,37 +1458,114 @@ fn start_execution(opts: &Opt) -> Receiver<Result<MatchInfo>> {
     // Set up the channel for the matchers to report their progress. The transmitters will be
     // cloned so they all use one channel the main thread can listen on.
     let (tx_matcher, rx_matcher) = unbounded();
-    let mut matcher_txs: Vec<Sender<PathBuf>> = Vec::new();
+    let options = MatcherOptions {
+        aborted: aborted.clone(),
+        ..matcher_options(opts)
+    };
+    let matcher_txs = spawn_matchers(num_threads, options, tx_matcher);
+
+    // Start up a finder on its own thread so the matchers can start on files as soon as they're
+    // found while this thread listens for results.
+    let (tx_finder, rx_finder) = bounded(1);
+    let finder_options = FinderOptions {
+        untracked: opts.untracked,
+        extensions: opts.extensions.clone(),
+        submodules: opts.submodule,
+        use_jpmigrate: opts.use_jpmigrate,
+        staged: opts.staged,
+        subdir: opts.subdir.clone(),
+        load_balance: opts.load_balance.unwrap_or_default(),
+        last_run,
+        aborted: aborted.clone(),
+    };
+    thread::Builder::new()
+        .name("finder".to_string())
+        .spawn(move || {
+            finder::Finder::new(finder_options).find_paths(matcher_txs, tx_finder);
+        })
+        .unwrap();
+
+    (rx_finder, rx_matcher, aborted)
+}
 
+/// Spawns a thread per matcher, each with its own channel to receive files on.
+///
+/// * num_threads - The number of matchers to spawn
+/// * options - The options for the matchers
+/// * tx_matcher - The transmitter the matchers report back on
+///
+/// Returns the transmitters to send files to the matchers with
+#[cfg(not(feature = "rayon"))]
+fn spawn_matchers(
+    num_threads: usize,
+    options: MatcherOptions,
+    tx_matcher: Sender<Result<MatchInfo, MigrationError>>,
+) -> Vec<Sender<PathBuf>> {
+    let mut matcher_txs: Vec<Sender<PathBuf>> = Vec::new();
     for i in 0..num_threads {
         let (tx_in, rx_in) = unbounded();
         matcher_txs.push(tx_in);
         let tx_main_clone = tx_matcher.clone();
+        let options = options.clone();
 
         // Spawn a new thread and kick off a matcher
         thread::Builder::new()
             .name("matcher".to_string())
             .spawn(move || {
-                matcher::Matcher::new(i, tx_main_clone).run(rx_in);
+                matcher::Matcher::new(i, tx_main_clone, options).run(rx_in);
             })
             .unwrap();
     }
-    // Drop this thread's transmitter so the channel doesn't remain open even when all the other
-    // threads have finished.
-    drop(tx_matcher);
+    // The transmitter is dropped here so the channel doesn't remain open even when all the
+    // matchers have finished.
+    matcher_txs
+}
 
-    // Start up a finder, still use channels despite it not being threaded.
-    let (tx_finder, rx_finder) = bounded(1);
-    finder::Finder::new().find_paths(matcher_txs, tx_finder);
-    let message = rx_finder.recv().unwrap();
-    if !opts.quiet {
-        println!(
-            "Found {} files (.gradle, .gradle.kts, .java, .kt, .pro, .xml)...",
-            message.total_files_found
-        );
-    }
+/// Spawns a rayon thread pool that migrates files as they're received on a single channel, so
+/// idle threads can steal work instead of waiting on their own channel. Files aren't read through
+/// io_uring in batches this way.
+///
+/// * num_threads - The number of threads in the pool
+/// * options - The options for the matchers
+/// * tx_matcher - The transmitter the matchers report back on
+///
+/// Returns the transmitter to send files to the pool with
+#[cfg(feature = "rayon")]
+fn spawn_matchers(
+    num_threads: usize,
+    options: MatcherOptions,
+    tx_matcher: Sender<Result<MatchInfo, MigrationError>>,
+) -> Vec<Sender<PathBuf>> {
+    use rayon::iter::{ParallelBridge, ParallelIterator};
 
-    rx_matcher
+    let (tx_in, rx_in) = unbounded();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(|_| "matcher".to_string())
+        .build()
+        .unwrap();
+    thread::Builder::new()
+        .name("matchers".to_string())
+        .spawn(move || {
+            // Matchers only hold their mappings, so one per thread in the pool can be shared
+            let matchers: Vec<Matcher> = (0..num_threads)
+                .map(|i| Matcher::new(i, tx_matcher.clone(), options.clone()))
+                .collect();
+            drop(tx_matcher);
+            let aborted = options.aborted;
+            pool.install(|| {
+                rx_in
+                    .into_iter()
+                    .take_while(|_| !aborted.load(Ordering::SeqCst))
+                    .par_bridge()
+                    .for_each(|path| {
+                        let i = rayon::current_thread_index().unwrap_or_default();
+                        matchers[i].process(path);
+                    })
+            });
+        })
+        .unwrap();
+    vec![tx_in]
 }
 
 /// Listens to the given Receiver for MatchInfo messages, printing useful output to stdout and
@@ -102,63 +1573,549 @@ fn start_execution(opts: &Opt) -> Receiver<Result<MatchInfo>> {
 ///
 /// * start - The instant the program started
 /// * opts - The CLI options passed in
-/// * rx_matcher - The Receiver to listen to
-fn listen_for_messages(start: Instant, opts: &Opt, rx_matcher: Receiver<Result<MatchInfo>>) {
+/// * rx_finder - The Receiver the finder reports on
+/// * rx_matcher - The Receiver the matchers report on
+/// * aborted - Set on the first error with --abort-on-error to stop the finder and matchers
+///
+/// Returns the Summary of the run
+fn listen_for_messages(
+    start: Instant,
+    opts: &Opt,
+    mut rx_finder: Receiver<FinderInfo>,
+    rx_matcher: Receiver<Result<MatchInfo, MigrationError>>,
+    aborted: &AtomicBool,
+) -> Summary {
+    let mut stderr = StandardStream::stderr(color_choice(opts));
     let mut num_files_changed = 0;
     let mut num_changes = 0;
-    while let Ok(message) = rx_matcher.recv() {
+    let mut num_errors = 0;
+    let mut num_files_over_max = 0;
+    let mut found_warnings = false;
+    let mut proposed_changes = Vec::new();
+    let mut num_files_processed: u64 = 0;
+    let mut num_bytes_processed: u64 = 0;
+    let mut changed_files: Vec<PathBuf> = Vec::new();
+    let mut migrated_files: Vec<PathBuf> = Vec::new();
+    let mut changelog: Vec<ChangelogEntry> = Vec::new();
+    let mut files: Vec<FileSummary> = Vec::new();
+    let mut artifacts: BTreeMap<String, String> = BTreeMap::new();
+    let mut replacement_classes: BTreeSet<String> = BTreeSet::new();
+    let mut csv = (opts.output_format == Some(OutputFormat::Csv))
+        .then(|| csv::Writer::from_writer(io::stdout()));
+    let mut category_counts: BTreeMap<Category, usize> = BTreeMap::new();
+    // The slowest files to migrate along with the matcher that migrated them, slowest first
+    let mut slowest_files: Vec<(Duration, PathBuf, usize)> = Vec::new();
+    // Unique star imports mapped to the number of files they were found in
+    let mut star_imports: BTreeMap<String, usize> = BTreeMap::new();
+    // Matcher IDs mapped to the number of files they processed and replacements they made
+    let mut thread_stats: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    // Mapping patterns mapped to the number of times they were applied across all files
+    let mut mapping_hits: HashMap<String, usize> = HashMap::new();
+    // Results held back with --sort-output until every file is done, then handled in order
+    let mut buffered: Vec<Result<MatchInfo, MigrationError>> = Vec::new();
+    let mut sorted: Option<std::vec::IntoIter<_>> = None;
+    loop {
+        let message = match &mut sorted {
+            Some(sorted) => match sorted.next() {
+                Some(message) => message,
+                None => break,
+            },
+            None => select! {
+                recv(rx_finder) -> info => {
+                    if let Ok(info) = info {
+                        print_finder_info(opts, &info);
+                    }
+                    // The finder only ever reports once so stop listening to it
+                    rx_finder = never();
+                    continue;
+                }
+                recv(rx_matcher) -> message => match message {
+                    Ok(message) if opts.sort_output => {
+                        // Don't hold up aborting until every result is in
+                        let failed = message
+                            .as_ref()
+                            .is_err_and(|e| !matches!(e, MigrationError::TooManyReplacements(_)));
+                        if opts.abort_on_error && failed {
+                            aborted.store(true, Ordering::SeqCst);
+                        }
+                        buffered.push(message);
+                        continue;
+                    }
+                    Ok(message) => message,
+                    Err(_) if opts.sort_output => {
+                        buffered.sort_by(|a, b| message_path(a).cmp(&message_path(b)));
+                        sorted = Some(std::mem::take(&mut buffered).into_iter());
+                        continue;
+                    }
+                    Err(_) => break,
+                },
+            },
+        };
+
         match message {
-            Ok(match_info) => {
-                if match_info.matches_found > 0 {
-                    num_changes += match_info.matches_found;
-                    num_files_changed += 1;
+            Ok(mut match_info) => {
+                let stats = thread_stats.entry(match_info.matcher_id).or_default();
+                stats.0 += 1;
+                stats.1 += match_info.matches_found;
+
+                if match_info.skipped_too_large {
+                    if !opts.quiet {
+                        print_warning(
+                            &mut stderr,
+                            Color::Yellow,
+                            &format!(
+                                "Skipped {} since it is larger than the max file size ({} bytes)",
+                                match_info.path.to_string_lossy(),
+                                match_info.file_size
+                            ),
+                        );
+                    }
+                    continue;
+                }
+                if match_info.skipped_symlink {
+                    if !opts.quiet {
+                        print_warning(
+                            &mut stderr,
+                            Color::Yellow,
+                            &format!(
+                                "Skipped {} since it is a symlink",
+                                match_info.path.to_string_lossy()
+                            ),
+                        );
+                    }
+                    continue;
+                }
+
+                num_files_processed += 1;
+                num_bytes_processed += match_info.file_size;
+                if opts.verbose {
+                    slowest_files.push((
+                        match_info.duration,
+                        match_info.path.clone(),
+                        match_info.matcher_id,
+                    ));
+                    slowest_files.sort_unstable_by_key(|file| std::cmp::Reverse(file.0));
+                    slowest_files.truncate(NUM_SLOWEST_FILES);
+                }
+
+                // Remember files that won't need any more attention so they can be skipped next
+                // time. Changes that weren't written still have to be made on a later run.
+                let needs_attention = !match_info.matched_star_imports.is_empty()
+                    || !match_info.unrecognized_patterns.is_empty()
+                    || !match_info.artifacts_found.is_empty()
+                    || !match_info.low_confidence_replacements.is_empty()
+                    || !match_info.deprecated_replacements.is_empty()
+                    || match_info.skipped_high_count;
+                let changes_written = match_info.matches_found == 0
+                    || (match_info.dry_run_result.is_none() && opts.output_dir.is_none());
+                found_warnings |= !match_info.matched_star_imports.is_empty()
+                    || !match_info.artifacts_found.is_empty();
+                replacement_classes.append(&mut match_info.replacement_classes);
+                if opts.since_last_run && !needs_attention && changes_written {
+                    migrated_files.push(match_info.path.clone());
+                }
+                if opts
+                    .output_format
+                    .is_some_and(|format| format != OutputFormat::Text)
+                {
+                    let file = FileSummary {
+                        path: match_info.path.to_string_lossy().into_owned(),
+                        replacements: if match_info.skipped_high_count {
+                            0
+                        } else {
+                            match_info.matches_found
+                        },
+                        artifacts_count: match_info.artifacts_found.len(),
+                        star_imports_count: match_info.matched_star_imports.len(),
+                    };
+                    // CSV rows are written as they come in, JSON is written all at once at the end
+                    if !file.is_empty() {
+                        match &mut csv {
+                            Some(csv) => {
+                                let _ = csv.serialize(file);
+                            }
+                            None => files.push(file),
+                        }
+                    }
+                }
+                if opts.changelog.is_some() {
+                    changelog.push(ChangelogEntry {
+                        path: match_info.path.clone(),
+                        replacements: if match_info.skipped_high_count {
+                            0
+                        } else {
+                            match_info.matches_found
+                        },
+                        artifacts: match_info
+                            .artifacts_found
+                            .iter()
+                            .map(|artifact| {
+                                format!(
+                                    "{} => {}",
+                                    artifact.mapping.pattern.as_str(),
+                                    artifact.mapping.replacement_with_version()
+                                )
+                            })
+                            .collect(),
+                        star_imports: match_info.matched_star_imports.clone(),
+                    });
+                }
+                for (pattern, hits) in &match_info.mapping_hits {
+                    *mapping_hits.entry(pattern.clone()).or_default() += hits;
                 }
 
-                // Print out any star imports found
-                if !match_info.matched_star_imports.is_empty() {
-                    eprintln!(
-                        "Found {} star import(s) that must be updated in {}:",
-                        match_info.matched_star_imports.len(),
-                        match_info.path.to_string_lossy()
+                // Show how much of the file even looked like it referenced an old package
+                if opts.verbose && match_info.total_lines > 0 {
+                    println!(
+                        "{}: {:.1}% difficulty, {} of {} line(s) matched a pattern check, {} \
+                         unchanged",
+                        match_info.path.to_string_lossy(),
+                        match_info.difficulty(),
+                        match_info.lines_with_potential_matches,
+                        match_info.total_lines,
+                        match_info.unchanged_lines
                     );
+                }
 
+                if match_info.skipped_high_count {
+                    if !opts.quiet {
+                        print_warning(
+                            &mut stderr,
+                            Color::Yellow,
+                            &format!(
+                                "Skipped {} since it has {} replacement(s), more than the threshold \
+                                 of {}. It might be generated, check it by hand.",
+                                match_info.path.to_string_lossy(),
+                                match_info.matches_found,
+                                opts.replacement_count_threshold.unwrap_or_default()
+                            ),
+                        );
+                    }
+                } else if match_info.matches_found > 0 {
+                    num_changes += match_info.matches_found;
+                    for (category, count) in &match_info.category_counts {
+                        *category_counts.entry(*category).or_default() += count;
+                    }
+                    num_files_changed += 1;
+                    changed_files.push(match_info.path.clone());
+
+                    // List out everything that needs to be fixed when checking
+                    if opts.check || opts.only_files {
+                        println!("{}", match_info.path.to_string_lossy());
+                    }
+
+                    // Show the changes in the style of a patch
+                    if opts.interactive {
+                        proposed_changes
+                            .push((match_info.path.clone(), match_info.replaced_lines.clone()));
+                    } else if opts.verbose {
+                        let _ = interactive::write_diff(
+                            &mut io::stdout(),
+                            &match_info.path,
+                            &match_info.replaced_lines,
+                        );
+                    }
+                } else if opts.include_artifact_files && !match_info.artifacts_found.is_empty() {
+                    println!("{}", match_info.path.to_string_lossy());
+                }
+
+                // Print out any star imports found, or save them for later when summarizing
+                if opts.summarize_star_imports {
+                    for line in &match_info.matched_star_imports {
+                        *star_imports.entry(line.trim().to_string()).or_insert(0) += 1;
+                    }
+                } else if let Some(format) = opts.diagnostic_format {
                     match_info
                         .matched_star_imports
                         .iter()
-                        .for_each(|line| eprintln!("  * {}", line));
+                        .zip(&match_info.star_import_line_numbers)
+                        .for_each(|(line, line_number)| {
+                            let message =
+                                format!("star import must be migrated by hand: {}", line.trim());
+                            eprintln!(
+                                "{}",
+                                diagnostic::format_warning(
+                                    format,
+                                    &match_info.path,
+                                    *line_number,
+                                    &message
+                                )
+                            );
+                        });
                 }
 
-                // Print out any artifacts found that need to be updated
-                if !match_info.artifacts_found.is_empty() {
-                    // Print to error so it can't be ignored
-                    eprintln!(
-                        "Found {} artifact(s) that must be updated in {}:",
-                        match_info.artifacts_found.len(),
-                        match_info.path.to_string_lossy()
-                    );
-                    match_info.artifacts_found.iter().for_each(|mapping| {
-                        // The longest artifact is 59 characters so pad for that
+                // Print out any artifacts found that need to be updated, or save them for the report
+                if opts
+                    .artifact_report_format
+                    .is_some_and(|format| format != ArtifactReportFormat::Text)
+                {
+                    for artifact in &match_info.artifacts_found {
+                        artifacts.insert(
+                            String::from(artifact.mapping.pattern.as_str()),
+                            artifact.mapping.replacement_with_version(),
+                        );
+                    }
+                } else if let Some(format) = opts.diagnostic_format {
+                    match_info.artifacts_found.iter().for_each(|artifact| {
+                        let mut message = format!(
+                            "{} must be updated to {}",
+                            artifact.mapping.pattern.as_str(),
+                            artifact.mapping.replacement_with_version()
+                        );
+                        if let Some(version) = &artifact.version {
+                            message.push_str(&format!(" (currently {})", version));
+                        }
                         eprintln!(
-                            "  * {:<60}=> {}",
-                            mapping.pattern.as_str(),
-                            mapping.replacement
-                        )
+                            "{}",
+                            diagnostic::format_warning(
+                                format,
+                                &match_info.path,
+                                artifact.line_number,
+                                &message
+                            )
+                        );
                     });
                 }
+
+                // Print everything else that has to be looked at by hand, unless it was already
+                // reported another way above
+                for warning in match_info.warnings() {
+                    let reported = match warning.kind {
+                        WarningKind::StarImports => {
+                            opts.summarize_star_imports || opts.diagnostic_format.is_some()
+                        }
+                        WarningKind::Artifacts => {
+                            opts.diagnostic_format.is_some()
+                                || opts
+                                    .artifact_report_format
+                                    .is_some_and(|format| format != ArtifactReportFormat::Text)
+                        }
+                        _ => false,
+                    };
+                    if !reported {
+                        // Artifacts are printed in red so they can't be ignored
+                        let color = match warning.kind {
+                            WarningKind::Artifacts => Color::Red,
+                            _ => Color::Yellow,
+                        };
+                        print_warning(&mut stderr, color, &warning.heading);
+                        for item in &warning.items {
+                            eprintln!("  * {}", item);
+                        }
+                    }
+                }
+            }
+            Err(MigrationError::TooManyReplacements(_)) => num_files_over_max += 1,
+            Err(e) => {
+                num_errors += 1;
+                if !opts.ignore_errors {
+                    eprintln!("{}", e);
+                }
+                // Results already on their way are still printed while everything winds down
+                if opts.abort_on_error {
+                    aborted.store(true, Ordering::SeqCst);
+                }
             }
-            Err(e) => eprintln!("{}", e),
         };
     }
 
+    if let Some(mut csv) = csv {
+        let _ = csv.flush();
+    }
+
+    if num_files_over_max > 0 {
+        print_warning(
+            &mut stderr,
+            Color::Red,
+            &format!(
+                "Stopped writing changes after {} replacement(s), {} file(s) were left untouched",
+                opts.max_replacements.unwrap_or_default(),
+                num_files_over_max
+            ),
+        );
+    }
+
+    if !star_imports.is_empty() {
+        print_warning(
+            &mut stderr,
+            Color::Yellow,
+            &format!(
+                "Found {} unique star import(s) that must be updated:",
+                star_imports.len()
+            ),
+        );
+        star_imports
+            .iter()
+            .for_each(|(line, count)| eprintln!("  * {} ({} occurrence(s))", line, count));
+    }
+
+    if opts.profile && !mapping_hits.is_empty() {
+        // Most used mappings first, ties broken alphabetically so the output is stable
+        let mut mapping_hits: Vec<(String, usize)> = mapping_hits.into_iter().collect();
+        mapping_hits.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        println!("{:>8}  Mapping", "Hits");
+        mapping_hits
+            .iter()
+            .for_each(|(pattern, hits)| println!("{:>8}  {}", hits, pattern));
+    }
+
+    // The matchers can finish up at the same time as the finder so make sure it still reports
+    if let Ok(info) = rx_finder.try_recv() {
+        print_finder_info(opts, &info);
+    }
+
     // Report final stats of the run
     let duration = start.elapsed();
-    if !opts.quiet {
+    if !opts.quiet && !opts.only_artifacts {
         println!(
-            "Replaced {} occurrence(s) in {} file(s) in {}.{}s!",
+            "{} {} occurrence(s) in {} file(s) in {}.{}s!",
+            if opts.dry_run() || opts.interactive {
+                "Would replace"
+            } else {
+                "Replaced"
+            },
             num_changes,
             num_files_changed,
             duration.as_secs(),
             duration.subsec_millis() / 10
         );
+        if num_changes > 0 {
+            let counts: Vec<String> = [
+                Category::Support,
+                Category::Arch,
+                Category::Databind,
+                Category::Kotlinx,
+            ]
+            .iter()
+            .map(|category| {
+                format!(
+                    "{} {}",
+                    category_counts.get(category).unwrap_or(&0),
+                    category
+                )
+            })
+            .collect();
+            println!("  {} occurrence(s)", counts.join(", "));
+        }
+    }
+
+    if opts.verbose && num_files_processed > 0 {
+        println!(
+            "Processed {} bytes across {} file(s), {} bytes per file on average",
+            num_bytes_processed,
+            num_files_processed,
+            num_bytes_processed / num_files_processed
+        );
+        println!("Slowest file(s):");
+        for (duration, path, matcher_id) in &slowest_files {
+            println!(
+                "{:>10.2}ms  {} (thread {})",
+                duration.as_secs_f64() * 1000.0,
+                path.to_string_lossy(),
+                matcher_id
+            );
+        }
+    }
+
+    if opts.thread_stats {
+        for (matcher_id, (num_files, num_replacements)) in &thread_stats {
+            println!(
+                "Thread {}: {} file(s), {} replacement(s)",
+                matcher_id, num_files, num_replacements
+            );
+        }
+    }
+
+    if opts.debug() {
+        eprintln!(
+            "Peak memory mapped at once: {} bytes",
+            matcher::peak_mapped_bytes()
+        );
     }
+
+    if opts.ignore_errors && num_errors > 0 {
+        eprintln!("Ignored errors in {} file(s)", num_errors);
+    }
+
+    Summary {
+        num_changes,
+        changed_files,
+        changelog,
+        files,
+        artifacts,
+        replacement_classes,
+        exceeded_max_replacements: num_files_over_max > 0,
+        found_warnings,
+        proposed_changes,
+        migrated_files,
+    }
+}
+
+/// The path of the file a matcher's result is for.
+///
+/// * message - The result of migrating a file
+fn message_path(message: &Result<MatchInfo, MigrationError>) -> Option<&Path> {
+    match message {
+        Ok(match_info) => Some(&match_info.path),
+        Err(e) => e.path(),
+    }
+}
+
+/// Prints out what the finder found.
+///
+/// * opts - The CLI options passed in
+/// * info - The info the finder reported
+fn print_finder_info(opts: &Opt, info: &FinderInfo) {
+    if !opts.quiet {
+        let mut extensions = String::from(".aidl, .gradle, .gradle.kts, .java, .kt, .pro, .xml");
+        for extension in &opts.extensions {
+            extensions.push_str(", ");
+            if !extension.starts_with('.') {
+                extensions.push('.');
+            }
+            extensions.push_str(extension);
+        }
+        println!("Found {} files ({})...", info.total_files_found, extensions);
+        if info.unchanged_files > 0 {
+            println!(
+                "Skipped {} file(s) unchanged since the last run",
+                info.unchanged_files
+            );
+        }
+        if let Some((path, size)) = info.largest_file.as_ref().filter(|_| opts.verbose) {
+            println!(
+                "Largest file is {} ({} bytes)",
+                path.to_string_lossy(),
+                size
+            );
+        }
+    }
+}
+
+/// Determines whether warnings should be colored. Explicit flags take priority, then the NO_COLOR
+/// environment variable (https://no-color.org), and finally whether stderr is a terminal.
+///
+/// * opts - The CLI options passed in
+fn color_choice(opts: &Opt) -> ColorChoice {
+    if opts.no_color {
+        ColorChoice::Never
+    } else if opts.color {
+        ColorChoice::Always
+    } else if env::var_os("NO_COLOR").is_some() || !io::stderr().is_terminal() {
+        ColorChoice::Never
+    } else {
+        ColorChoice::Auto
+    }
+}
+
+/// Prints a single line warning to stderr in the given color.
+///
+/// * stderr - The stream to print to
+/// * color - The color of the warning
+/// * message - The warning to print
+fn print_warning(stderr: &mut StandardStream, color: Color, message: &str) {
+    let _ = stderr.set_color(ColorSpec::new().set_fg(Some(color)));
+    let _ = write!(stderr, "{}", message);
+    let _ = stderr.reset();
+    let _ = writeln!(stderr);
 }
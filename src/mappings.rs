@@ -1,9 +1,24 @@
 use lazy_static::lazy_static;
 use regex::{Regex, RegexSet};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
-use serde_regex;
+use sha2::{Digest, Sha256};
 
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, ErrorKind};
+use std::iter;
+#[cfg(not(feature = "rayon"))]
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::str;
+use std::sync::OnceLock;
+#[cfg(not(feature = "rayon"))]
+use std::thread;
 use std::vec::Vec;
 
 // Include the csv mapping files. They are separated by the first difference in their package
@@ -15,25 +30,544 @@ const SUPPORT_MAPPING_CSV: &str = include_str!("../android_support_mappings.csv"
 const DATABIND_MAPPING_CSV: &str = include_str!("../android_databinding_mappings.csv");
 const ARCH_MAPPING_CSV: &str = include_str!("../android_arch_mappings.csv");
 
+// The kotlin-android-extensions plugin is deprecated alongside the support library. Its Parcelize
+// annotations moved to the kotlin-parcelize plugin and can be mapped like any other class, while
+// synthetic view properties have to be rewritten with view binding so they're only reported.
+const KOTLINX_MAPPING_CSV: &str = include_str!("../kotlin_extensions_mappings.csv");
+
 // Also include the artifact mappings so it's easy to know what packages you actually need to
 // replace. Since it's quite a bit more complex than just find and replace for the artifacts,
 // printing out the ones actually used in the project is good enough.
 const ARTIFACT_MAPPING_CSV: &str = include_str!("../android_artifact_mappings.csv");
 
-#[derive(Debug, Deserialize)]
+// Every mapping CSV by file name along with the version compiled into the binary
+const MAPPING_CSVS: [(&str, &str); 5] = [
+    ("android_support_mappings.csv", SUPPORT_MAPPING_CSV),
+    ("android_databinding_mappings.csv", DATABIND_MAPPING_CSV),
+    ("android_arch_mappings.csv", ARCH_MAPPING_CSV),
+    ("kotlin_extensions_mappings.csv", KOTLINX_MAPPING_CSV),
+    ("android_artifact_mappings.csv", ARTIFACT_MAPPING_CSV),
+];
+
+// Rows of a mapping CSV in a mappings directory can pull in the rows of another CSV, so large
+// custom mappings can be split up. Includes are limited in depth in case of a mistake.
+const INCLUDE_DIRECTIVE: &str = "$include";
+const MAX_INCLUDE_DEPTH: usize = 5;
+
+// The mappings can also be updated without a new release by loading them from a directory at
+// runtime. It has to be set before any of the mappings below are first used.
+static MAPPINGS_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Load mapping CSVs from the given directory instead of the ones compiled into the binary. Any
+/// CSV missing from the directory still uses the compiled in version.
+///
+/// * `dir` - The directory containing the mapping CSVs
+pub fn set_mappings_dir(dir: PathBuf) {
+    let _ = MAPPINGS_DIR.set(dir);
+}
+
+/// The directory set with [`set_mappings_dir`], if any.
+fn mappings_dir() -> Option<&'static Path> {
+    MAPPINGS_DIR.get().map(PathBuf::as_path)
+}
+
+/// Get the contents of a mapping CSV, preferring the one in the mappings directory if any.
+///
+/// * `dir` - The directory to look for the CSV in, if any
+/// * `file_name` - The name of the CSV
+/// * `compiled` - The contents of the CSV compiled into the binary
+fn mapping_csv(dir: Option<&Path>, file_name: &str, compiled: &'static str) -> Cow<'static, str> {
+    let path = match dir {
+        Some(dir) => dir.join(file_name),
+        None => return Cow::Borrowed(compiled),
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(contents) if contents.contains(INCLUDE_DIRECTIVE) => {
+            Cow::Owned(resolve_includes(&path, &contents).unwrap_or_else(|e| panic!("{}", e)))
+        }
+        Ok(contents) => Cow::Owned(contents),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Cow::Borrowed(compiled),
+        Err(e) => panic!("Failed to read {}: {}", path.to_string_lossy(), e),
+    }
+}
+
+/// Merge every CSV included with a `$include,<path>` row into the given CSV. Paths are relative to
+/// the CSV including them and included CSVs can include others in turn, up to
+/// [`MAX_INCLUDE_DEPTH`] deep.
+///
+/// * `path` - The path of the CSV
+/// * `contents` - The contents of the CSV
+///
+/// Returns the contents of the merged CSV, with the header of the given one
+fn resolve_includes(path: &Path, contents: &str) -> Result<String, String> {
+    let csv_error = |e: csv::Error| format!("{}: {}", path.to_string_lossy(), e);
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let headers = rdr.headers().map_err(csv_error)?.clone();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&headers).map_err(csv_error)?;
+
+    let mut included = vec![path.canonicalize().map_err(|e| e.to_string())?];
+    write_included_records(path, contents, headers.len(), &mut included, &mut writer)?;
+
+    let output = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(output).map_err(|e| e.to_string())
+}
+
+/// Write out every record of a CSV, following any includes along the way.
+///
+/// * `path` - The path of the CSV
+/// * `contents` - The contents of the CSV
+/// * `num_fields` - The number of fields in the header, shorter records are padded to match
+/// * `included` - The canonical paths of the CSVs currently being included, to detect cycles
+/// * `writer` - The writer for the merged CSV
+fn write_included_records(
+    path: &Path,
+    contents: &str,
+    num_fields: usize,
+    included: &mut Vec<PathBuf>,
+    writer: &mut csv::Writer<Vec<u8>>,
+) -> Result<(), String> {
+    let csv_error = |e: csv::Error| format!("{}: {}", path.to_string_lossy(), e);
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    for result in rdr.records() {
+        let record = result.map_err(csv_error)?;
+        if record.get(0) != Some(INCLUDE_DIRECTIVE) {
+            let padding = num_fields.saturating_sub(record.len());
+            let fields = record.iter().chain(iter::repeat_n("", padding));
+            writer.write_record(fields).map_err(csv_error)?;
+            continue;
+        }
+
+        let include = path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(record.get(1).unwrap_or_default());
+        if included.len() > MAX_INCLUDE_DEPTH {
+            return Err(format!(
+                "{}: can't include {}, includes are nested more than {} deep",
+                path.to_string_lossy(),
+                include.to_string_lossy(),
+                MAX_INCLUDE_DEPTH
+            ));
+        }
+        let read_error = |e: io::Error| {
+            format!(
+                "{}: failed to include {}: {}",
+                path.to_string_lossy(),
+                include.to_string_lossy(),
+                e
+            )
+        };
+        let canonical = include.canonicalize().map_err(read_error)?;
+        if included.contains(&canonical) {
+            return Err(format!(
+                "{}: including {} would be a cycle",
+                path.to_string_lossy(),
+                include.to_string_lossy()
+            ));
+        }
+        let included_contents = fs::read_to_string(&include).map_err(read_error)?;
+
+        included.push(canonical);
+        write_included_records(&include, &included_contents, num_fields, included, writer)?;
+        included.pop();
+    }
+
+    Ok(())
+}
+
+/// The kind of package a mapping migrates, one per mapping CSV
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Category {
+    /// android.support classes
+    Support,
+    /// android.arch classes
+    Arch,
+    /// android.databinding classes
+    Databind,
+    /// kotlinx.android classes from the kotlin-android-extensions plugin
+    Kotlinx,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Category::Support => write!(f, "support"),
+            Category::Arch => write!(f, "arch"),
+            Category::Databind => write!(f, "databind"),
+            Category::Kotlinx => write!(f, "kotlinx"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct Mapping {
     #[serde(with = "serde_regex", rename = "Support Library class")]
     pub pattern: Regex,
     #[serde(rename = "Android X class")]
     pub replacement: String,
+    /// Optional human readable explanation of the change, e.g. which module the class moved to
+    #[serde(rename = "Description", default)]
+    pub description: Option<String>,
+    /// How sure the mapping is to be right, from 0 to 1. Classes that were split across several
+    /// AndroidX modules can only be mapped to one of them, so those replacements should be checked.
+    #[serde(rename = "Confidence", default)]
+    pub confidence: Option<f32>,
+    /// Deprecation notice for replacements that have since moved again in a later AndroidX
+    /// release, printed whenever the mapping is applied
+    #[serde(rename = "Deprecated", default)]
+    pub deprecated: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+impl Mapping {
+    /// Whether replacements made with this mapping might be wrong and should be checked by hand.
+    pub fn is_uncertain(&self) -> bool {
+        self.confidence.is_some_and(|confidence| confidence < 1.0)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct ArtifactMapping {
     #[serde(with = "serde_regex", rename = "Old build artifact")]
     pub pattern: Regex,
     #[serde(rename = "AndroidX build artifact")]
     pub replacement: String,
+    /// The recommended version of the AndroidX artifact
+    #[serde(rename = "AndroidX version", default)]
+    pub version: Option<String>,
+}
+
+// Mappings are the same if they replace the same pattern with the same thing, no matter how
+// they're described, so the same mapping loaded from several CSVs can be deduplicated.
+impl PartialEq for Mapping {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() && self.replacement == other.replacement
+    }
+}
+
+impl Eq for Mapping {}
+
+impl Hash for Mapping {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pattern.as_str().hash(state);
+    }
+}
+
+impl PartialEq for ArtifactMapping {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() && self.replacement == other.replacement
+    }
+}
+
+impl Eq for ArtifactMapping {}
+
+impl Hash for ArtifactMapping {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pattern.as_str().hash(state);
+    }
+}
+
+impl ArtifactMapping {
+    /// The full replacement artifact, including the recommended version if there is one.
+    pub fn replacement_with_version(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}:{}", self.replacement, version),
+            None => self.replacement.clone(),
+        }
+    }
+}
+
+/// Check every mapping CSV for mistakes that would lead to wrong or missing migrations.
+///
+/// Returns a description of each issue that was found
+pub fn validate_mappings() -> Vec<String> {
+    MAPPING_CSVS
+        .iter()
+        .flat_map(|(file_name, compiled)| {
+            validate_mapping_csv(file_name, &mapping_csv(mappings_dir(), file_name, compiled))
+        })
+        .collect()
+}
+
+/// A SHA-256 hash of every mapping CSV in use, so results can be thrown out when the mappings
+/// change.
+///
+/// Returns the hash as a hex string
+pub fn mappings_hash() -> String {
+    let mut hasher = Sha256::new();
+    for (file_name, compiled) in MAPPING_CSVS.iter() {
+        hasher.update(mapping_csv(mappings_dir(), file_name, compiled).as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Check a single mapping CSV for duplicate patterns, patterns that are never reached, patterns
+/// that aren't valid regex and replacements that are still a different old package.
+///
+/// * `file_name` - The name of the CSV to use in the issues
+/// * `contents` - The contents of the CSV
+///
+/// Returns a description of each issue that was found
+fn validate_mapping_csv(file_name: &str, contents: &str) -> Vec<String> {
+    let mut issues = Vec::new();
+    let mut seen = HashSet::new();
+    let mut patterns: Vec<(u64, String, Regex)> = Vec::new();
+    let mut rdr = csv::Reader::from_reader(contents.as_bytes());
+    for result in rdr.records() {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                issues.push(format!("{}: {}", file_name, e));
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |position| position.line());
+        let pattern = record.get(0).unwrap_or_default();
+        let replacement = record.get(1).unwrap_or_default();
+
+        // Some classes intentionally kept their support library package and map to themselves
+        if replacement != pattern && OLD_PACKAGE_MATCH.is_match(replacement) {
+            issues.push(format!(
+                "{}:{}: replacement {} is still an old package",
+                file_name, line, replacement
+            ));
+        }
+        if !seen.insert(String::from(pattern)) {
+            issues.push(format!(
+                "{}:{}: duplicate pattern {}",
+                file_name, line, pattern
+            ));
+            continue;
+        }
+        match Regex::new(pattern) {
+            Ok(regex) => patterns.push((line, String::from(pattern), regex)),
+            Err(e) => issues.push(format!(
+                "{}:{}: pattern {} is not a valid regex: {}",
+                file_name, line, pattern, e
+            )),
+        }
+    }
+
+    // Mappings are checked longest pattern first and the first match wins, so a pattern is never
+    // reached if one that's at least as long also matches it.
+    for (line, pattern, _) in &patterns {
+        let shadowed_by = patterns.iter().find(|(_, other, regex)| {
+            other != pattern && other.len() >= pattern.len() && regex.is_match(pattern)
+        });
+        if let Some((other_line, other, _)) = shadowed_by {
+            issues.push(format!(
+                "{}:{}: pattern {} is never reached since {} on line {} matches first",
+                file_name, line, pattern, other, other_line
+            ));
+        }
+    }
+
+    issues
+}
+
+/// Print a warning for every replacement that isn't an AndroidX package, so a broken CSV update is
+/// noticed without stopping the migration.
+///
+/// * `file_name` - The name of the CSV the mappings were loaded from
+/// * `mappings` - The pattern and replacement of each mapping
+fn warn_invalid_replacements<'a>(
+    file_name: &str,
+    mappings: impl Iterator<Item = (&'a str, &'a str)>,
+) {
+    for replacement in invalid_replacements(mappings) {
+        eprintln!(
+            "Warning: {} maps to {} which is not an AndroidX package",
+            file_name, replacement
+        );
+    }
+}
+
+/// Find every replacement that isn't an AndroidX package. Classes that kept their support library
+/// package and map to themselves are left out.
+///
+/// * `mappings` - The pattern and replacement of each mapping
+///
+/// Returns the invalid replacements
+fn invalid_replacements<'a>(mappings: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<&'a str> {
+    mappings
+        .filter(|(pattern, replacement)| {
+            pattern != replacement && !ANDROIDX_MATCH.is_match(replacement)
+        })
+        .map(|(_, replacement)| replacement)
+        .collect()
+}
+
+/// Print a warning for every mapping whose replacement is matched again by another mapping, which
+/// would migrate the same reference twice, e.g. when running again.
+///
+/// * `file_name` - The name of the CSV the mappings were loaded from
+/// * `mappings` - The mappings loaded from the CSV
+fn warn_overlapping_mappings(file_name: &str, mappings: &[Mapping]) {
+    for (mapping, other) in overlapping_mappings(mappings) {
+        eprintln!(
+            "Warning: {} maps {} to {} which is migrated again by {}",
+            file_name, mapping.pattern, mapping.replacement, other.pattern
+        );
+    }
+}
+
+/// Find every pair of mappings where the replacement of the first is matched by the pattern of
+/// the second. Only replacements that are still an old package can be matched again, so the rest
+/// are skipped without checking them against every other mapping. Classes that kept their support
+/// library package and map to themselves are left out.
+///
+/// * `mappings` - The mappings to check
+///
+/// Returns each mapping along with the one that matches its replacement
+fn overlapping_mappings(mappings: &[Mapping]) -> Vec<(&Mapping, &Mapping)> {
+    mappings
+        .iter()
+        .filter(|mapping| {
+            mapping.pattern.as_str() != mapping.replacement
+                && OLD_PACKAGE_MATCH.is_match(&mapping.replacement)
+        })
+        .flat_map(|mapping| {
+            mappings
+                .iter()
+                .filter(move |other| {
+                    !std::ptr::eq(*other, mapping) && other.pattern.is_match(&mapping.replacement)
+                })
+                .map(move |other| (mapping, other))
+        })
+        .collect()
+}
+
+/// Every set of mappings, loaded together so the CSVs can be parsed in parallel
+struct LoadedMappings {
+    support: Vec<Mapping>,
+    databind: Vec<Mapping>,
+    arch: Vec<Mapping>,
+    kotlinx: Vec<Mapping>,
+    artifacts: Vec<ArtifactMapping>,
+}
+
+impl LoadedMappings {
+    /// Load every mapping CSV at the same time on the rayon thread pool.
+    #[cfg(feature = "rayon")]
+    fn load() -> Self {
+        let ((support, databind), ((arch, kotlinx), artifacts)) = rayon::join(
+            || {
+                rayon::join(
+                    || load_mappings("android_support_mappings.csv", SUPPORT_MAPPING_CSV),
+                    || load_mappings("android_databinding_mappings.csv", DATABIND_MAPPING_CSV),
+                )
+            },
+            || {
+                rayon::join(
+                    || {
+                        rayon::join(
+                            || load_mappings("android_arch_mappings.csv", ARCH_MAPPING_CSV),
+                            || load_mappings("kotlin_extensions_mappings.csv", KOTLINX_MAPPING_CSV),
+                        )
+                    },
+                    || {
+                        load_artifact_mappings(
+                            "android_artifact_mappings.csv",
+                            ARTIFACT_MAPPING_CSV,
+                        )
+                    },
+                )
+            },
+        );
+        LoadedMappings {
+            support,
+            databind,
+            arch,
+            kotlinx,
+            artifacts,
+        }
+    }
+
+    /// Load every mapping CSV at the same time, each on its own thread. The support mappings are
+    /// by far the largest so they're loaded on the current thread while the others are spawned.
+    #[cfg(not(feature = "rayon"))]
+    fn load() -> Self {
+        // Re-raise a panic from loading a CSV as is so its message isn't lost
+        fn join<T>(handle: thread::ScopedJoinHandle<T>) -> T {
+            handle.join().unwrap_or_else(|e| panic::resume_unwind(e))
+        }
+
+        thread::scope(|scope| {
+            let databind = scope
+                .spawn(|| load_mappings("android_databinding_mappings.csv", DATABIND_MAPPING_CSV));
+            let arch = scope.spawn(|| load_mappings("android_arch_mappings.csv", ARCH_MAPPING_CSV));
+            let kotlinx = scope
+                .spawn(|| load_mappings("kotlin_extensions_mappings.csv", KOTLINX_MAPPING_CSV));
+            let artifacts = scope.spawn(|| {
+                load_artifact_mappings("android_artifact_mappings.csv", ARTIFACT_MAPPING_CSV)
+            });
+            let support = load_mappings("android_support_mappings.csv", SUPPORT_MAPPING_CSV);
+            LoadedMappings {
+                support,
+                databind: join(databind),
+                arch: join(arch),
+                kotlinx: join(kotlinx),
+                artifacts: join(artifacts),
+            }
+        })
+    }
+}
+
+/// Load the class mappings from a CSV, sorted with the longest pattern first, and warn about any
+/// that look wrong.
+///
+/// * `file_name` - The name of the CSV
+/// * `compiled` - The contents of the CSV compiled into the binary
+///
+/// Returns the sorted mappings
+fn load_mappings(file_name: &str, compiled: &'static str) -> Vec<Mapping> {
+    let mut vec: Vec<Mapping> = read_mapping_csv(file_name, compiled);
+    // Sort with longest pattern first. This prevents collisions and false mappings in cases
+    // like "Toolbar" and "ToolbarWidgetWrapper". Sorting is in theory less expensive to do
+    // once then have a more complex pattern that checks for boundaries.
+    vec.sort_unstable_by_key(|mapping| Reverse(mapping.pattern.as_str().len()));
+    warn_invalid_replacements(
+        file_name,
+        vec.iter()
+            .map(|mapping| (mapping.pattern.as_str(), mapping.replacement.as_str())),
+    );
+    warn_overlapping_mappings(file_name, &vec);
+    vec
+}
+
+/// Load the artifact mappings from a CSV, sorted with the longest pattern first, and warn about
+/// any that look wrong.
+///
+/// * `file_name` - The name of the CSV
+/// * `compiled` - The contents of the CSV compiled into the binary
+///
+/// Returns the sorted mappings
+fn load_artifact_mappings(file_name: &str, compiled: &'static str) -> Vec<ArtifactMapping> {
+    let mut vec: Vec<ArtifactMapping> = read_mapping_csv(file_name, compiled);
+    vec.sort_unstable_by_key(|mapping| Reverse(mapping.pattern.as_str().len()));
+    warn_invalid_replacements(
+        file_name,
+        vec.iter()
+            .map(|mapping| (mapping.pattern.as_str(), mapping.replacement.as_str())),
+    );
+    vec
+}
+
+/// Parse every row of a mapping CSV, preferring the one in the mappings directory if any.
+///
+/// * `file_name` - The name of the CSV
+/// * `compiled` - The contents of the CSV compiled into the binary
+///
+/// Returns the mappings in the order they're listed
+fn read_mapping_csv<T: DeserializeOwned>(file_name: &str, compiled: &'static str) -> Vec<T> {
+    let contents = mapping_csv(mappings_dir(), file_name, compiled);
+    let mut rdr = csv::Reader::from_reader(contents.as_bytes());
+    rdr.deserialize().map(|result| result.unwrap()).collect()
 }
 
 // Compiling the regex patterns is decently expensive and since they are used across all possible
@@ -47,20 +581,12 @@ pub struct ArtifactMapping {
 // "android.support" and if that isn't in the line then no other support library patterns will
 // match either.
 lazy_static! {
+    // Every mapping CSV is loaded at once the first time any of them is used, so they can all be
+    // parsed at the same time
+    static ref LOADED_MAPPINGS: LoadedMappings = LoadedMappings::load();
+
     // Regex and checks for support library changes
-    pub static ref SUPPORT_MAPPINGS: Vec<Mapping> = {
-        let mut vec = Vec::new();
-        let mut rdr = csv::Reader::from_reader(SUPPORT_MAPPING_CSV.as_bytes());
-        for result in rdr.deserialize() {
-            let mapping: Mapping = result.unwrap();
-            vec.push(mapping)
-        }
-        // Sort with longest pattern first. This prevents collisions and false mappings in cases
-        // like "Toolbar" and "ToolbarWidgetWrapper". Sorting is in theory less expensive to do
-        // once then have a more complex pattern that checks for boundaries.
-        vec.sort_unstable_by(|a, b| b.pattern.as_str().len().cmp(&a.pattern.as_str().len()));
-        vec
-    };
+    pub static ref SUPPORT_MAPPINGS: &'static [Mapping] = &LOADED_MAPPINGS.support;
     pub static ref SUPPORT_MIN_MATCH_LEN: usize =
         SUPPORT_MAPPINGS.last().unwrap().pattern.as_str().len();
     // Check most common boundaries to make sure false positives aren't found, e.g.
@@ -74,57 +600,51 @@ lazy_static! {
     // - ;: likely in lint baseline files for representing "<" or ">"
     // - (: full path as a parameter to a function
     // - [: kdoc link
-    pub static ref SUPPORT_MIN_MATCH: Regex = Regex::new(r#"[ </"@:\[';(]android\.support"#).unwrap();
+    // Every support class lives in a subpackage, so requiring the trailing "." skips prose and
+    // URLs that mention "android.support" in comments.
+    pub static ref SUPPORT_MIN_MATCH: Regex = Regex::new(r#"[ </"@:\[';(]android\.support\."#).unwrap();
 
     // Regex and checks for databinding changes
-    pub static ref DATABIND_MAPPINGS: Vec<Mapping> = {
-        let mut vec = Vec::new();
-        let mut rdr = csv::Reader::from_reader(DATABIND_MAPPING_CSV.as_bytes());
-        for result in rdr.deserialize() {
-            let mapping: Mapping = result.unwrap();
-            vec.push(mapping)
-        }
-        vec.sort_unstable_by(|a, b| b.pattern.as_str().len().cmp(&a.pattern.as_str().len()));
-        vec
-    };
+    pub static ref DATABIND_MAPPINGS: &'static [Mapping] = &LOADED_MAPPINGS.databind;
     pub static ref DATABIND_MIN_MATCH_LEN: usize =
         DATABIND_MAPPINGS.last().unwrap().pattern.as_str().len();
     pub static ref DATABIND_MIN_MATCH: Regex = Regex::new(r#"[ </"@:\[';(]android\.databinding"#).unwrap();
 
     // Regex and checks for architecture changes
-    pub static ref ARCH_MAPPINGS: Vec<Mapping> = {
-        let mut vec = Vec::new();
-        let mut rdr = csv::Reader::from_reader(ARCH_MAPPING_CSV.as_bytes());
-        for result in rdr.deserialize() {
-            let mapping: Mapping = result.unwrap();
-            vec.push(mapping)
-        }
-        vec.sort_unstable_by(|a, b| b.pattern.as_str().len().cmp(&a.pattern.as_str().len()));
-        vec
-    };
+    pub static ref ARCH_MAPPINGS: &'static [Mapping] = &LOADED_MAPPINGS.arch;
     pub static ref ARCH_MIN_MATCH_LEN: usize = ARCH_MAPPINGS.last().unwrap().pattern.as_str().len();
     pub static ref ARCH_MIN_MATCH: Regex = Regex::new(r#"[ </"@:\[';(]android\.arch"#).unwrap();
 
+    // Regex and checks for kotlin-android-extensions changes. Synthetic view imports match too so
+    // they're reported as unrecognized references that need to move to view binding.
+    pub static ref KOTLINX_MAPPINGS: &'static [Mapping] = &LOADED_MAPPINGS.kotlinx;
+    pub static ref KOTLINX_MIN_MATCH_LEN: usize =
+        KOTLINX_MAPPINGS.last().unwrap().pattern.as_str().len();
+    pub static ref KOTLINX_MIN_MATCH: Regex = Regex::new(r#"[ </"@:\[';(]kotlinx\.android\."#).unwrap();
+
     // Regex and checks for artifact changes
-    pub static ref ARTIFACT_MAPPINGS: Vec<ArtifactMapping> = {
-        let mut vec = Vec::new();
-        let mut rdr = csv::Reader::from_reader(ARTIFACT_MAPPING_CSV.as_bytes());
-        for result in rdr.deserialize() {
-            let mapping: ArtifactMapping = result.unwrap();
-            vec.push(mapping)
-        }
-        vec.sort_unstable_by(|a, b| b.pattern.as_str().len().cmp(&a.pattern.as_str().len()));
-        vec
-    };
+    pub static ref ARTIFACT_MAPPINGS: &'static [ArtifactMapping] = &LOADED_MAPPINGS.artifacts;
     pub static ref ARTIFACT_MIN_MATCH_LEN: usize =
         ARTIFACT_MAPPINGS.last().unwrap().pattern.as_str().len();
-    pub static ref ARTIFACT_MIN_MATCH: RegexSet = RegexSet::new(&[
+    pub static ref ARTIFACT_MIN_MATCH: RegexSet = RegexSet::new([
         r#"["']com\.android\.support[a-z\.]*:"#,
         r#"["']android\.arch[a-z\.]*:"#
     ]).unwrap();
 
-    // Match star import statements and proguard glob statements
-    pub static ref STAR_IMPORT_MATCH: Regex = Regex::new(r#"\.\*[;]?"#).unwrap();
+    // Replacements should never point back to an old package or artifact
+    static ref OLD_PACKAGE_MATCH: Regex =
+        Regex::new(r#"^(android\.(support|databinding|arch)|com\.android\.support|kotlinx\.android)[.:]"#).unwrap();
+
+    // Replacements are either AndroidX classes and artifacts, part of the material components, or
+    // part of the kotlin-parcelize plugin
+    static ref ANDROIDX_MATCH: Regex =
+        Regex::new(r#"^(androidx\.[a-z]|com\.google\.android\.material[.:]|kotlinx\.parcelize\.)[A-Za-z0-9._$:-]*$"#).unwrap();
+
+    // Match Java and Kotlin star import statements. Proguard globs such as
+    // "-dontwarn android.support.design.**" aren't imports and are reported as unrecognized
+    // references instead.
+    pub static ref STAR_IMPORT_MATCH: Regex =
+        Regex::new(r#"^\s*import\s+(static\s+)?[\w.]+\.\*\s*;?"#).unwrap();
 }
 
 #[cfg(test)]
@@ -153,6 +673,17 @@ mod tests {
         assert_eq!(*DATABIND_MIN_MATCH_LEN, size)
     }
 
+    #[test]
+    fn kotlinx_mappings_is_sorted_longest_to_shortest() {
+        let mut size = KOTLINX_MAPPINGS.first().unwrap().pattern.as_str().len();
+
+        for mapping in KOTLINX_MAPPINGS.iter() {
+            assert!(mapping.pattern.as_str().len() <= size);
+            size = mapping.pattern.as_str().len();
+        }
+        assert_eq!(*KOTLINX_MIN_MATCH_LEN, size)
+    }
+
     #[test]
     fn arch_mappings_is_sorted_longest_to_shortest() {
         let mut size = ARCH_MAPPINGS.first().unwrap().pattern.as_str().len();
@@ -175,6 +706,327 @@ mod tests {
         assert_eq!(*ARTIFACT_MIN_MATCH_LEN, size)
     }
 
+    #[test]
+    fn description_defaults_to_none_when_column_is_missing() {
+        assert!(SUPPORT_MAPPINGS.iter().all(|m| m.description.is_none()))
+    }
+
+    #[test]
+    fn description_is_loaded_when_column_is_present() {
+        let csv = "Support Library class,Android X class,Description
+android.support.v4.app.NotificationCompat,androidx.core.app.NotificationCompat,Moved to core
+android.support.v4.app.Fragment,androidx.fragment.app.Fragment,
+";
+        let mappings: Vec<Mapping> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .map(|result| result.unwrap())
+            .collect();
+
+        assert_eq!(mappings[0].description.as_deref(), Some("Moved to core"));
+        assert_eq!(mappings[1].description, None);
+    }
+
+    #[test]
+    fn redeclared_mapping_is_deduplicated() {
+        let custom = "Support Library class,Android X class,Description
+android.support.v4.app.Fragment,androidx.fragment.app.Fragment,Moved to fragment
+android.support.example.Custom,androidx.example.Custom,
+";
+        let custom_mappings: Vec<Mapping> = csv::Reader::from_reader(custom.as_bytes())
+            .deserialize()
+            .map(|result| result.unwrap())
+            .collect();
+        let mappings: HashSet<Mapping> = SUPPORT_MAPPINGS
+            .iter()
+            .cloned()
+            .chain(custom_mappings)
+            .collect();
+
+        assert_eq!(mappings.len(), SUPPORT_MAPPINGS.len() + 1);
+    }
+
+    #[test]
+    fn mappings_with_different_replacements_are_not_equal() {
+        let csv = "Old build artifact,AndroidX build artifact
+com.android.support:car,androidx.car:car
+com.android.support:car,androidx.car:car-app
+com.android.support:car,androidx.car:car
+";
+        let mappings: Vec<ArtifactMapping> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .map(|result| result.unwrap())
+            .collect();
+
+        assert_ne!(mappings[0], mappings[1]);
+        assert_eq!(mappings[0], mappings[2]);
+    }
+
+    #[test]
+    fn built_in_mappings_do_not_overlap() {
+        assert!(overlapping_mappings(&SUPPORT_MAPPINGS).is_empty());
+        assert!(overlapping_mappings(&ARCH_MAPPINGS).is_empty());
+        assert!(overlapping_mappings(&DATABIND_MAPPINGS).is_empty());
+        assert!(overlapping_mappings(&KOTLINX_MAPPINGS).is_empty());
+    }
+
+    #[test]
+    fn replacement_matched_by_other_mapping_overlaps() {
+        let csv = "Support Library class,Android X class
+android.support.v4.widget.Space,android.support.v7.widget.Space
+android.support.v7.widget.Space,androidx.legacy.widget.Space
+android.support.v4.util.Pair,android.support.v4.util.Pair
+";
+        let mappings: Vec<Mapping> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .map(|result| result.unwrap())
+            .collect();
+        let overlapping = overlapping_mappings(&mappings);
+
+        assert_eq!(overlapping.len(), 1);
+        assert_eq!(overlapping[0].0, &mappings[0]);
+        assert_eq!(overlapping[0].1, &mappings[1]);
+    }
+
+    #[test]
+    fn confidence_is_loaded_when_column_is_present() {
+        let csv = "Support Library class,Android X class,Description,Confidence
+android.support.v4.graphics.drawable.DrawableCompat,androidx.core.graphics.drawable.DrawableCompat,,0.5
+android.support.v4.app.Fragment,androidx.fragment.app.Fragment,,
+";
+        let mappings: Vec<Mapping> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .map(|result| result.unwrap())
+            .collect();
+
+        assert!(mappings[0].is_uncertain());
+        assert!(!mappings[1].is_uncertain());
+        assert!(SUPPORT_MAPPINGS.iter().all(|m| !m.is_uncertain()));
+    }
+
+    #[test]
+    fn deprecated_is_loaded_when_column_is_present() {
+        let csv = "Support Library class,Android X class,Deprecated
+android.support.v4.content.LocalBroadcastManager,androidx.localbroadcastmanager.content.LocalBroadcastManager,LocalBroadcastManager is deprecated
+android.support.v4.app.Fragment,androidx.fragment.app.Fragment,
+";
+        let mappings: Vec<Mapping> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .map(|result| result.unwrap())
+            .collect();
+
+        assert_eq!(
+            mappings[0].deprecated.as_deref(),
+            Some("LocalBroadcastManager is deprecated")
+        );
+        assert_eq!(mappings[1].deprecated, None);
+    }
+
+    #[test]
+    fn artifact_versions_are_loaded() {
+        assert!(ARTIFACT_MAPPINGS.iter().all(|m| m.version.is_some()))
+    }
+
+    #[test]
+    fn artifact_replacement_includes_version() {
+        let mapping = ARTIFACT_MAPPINGS
+            .iter()
+            .find(|m| m.pattern.as_str() == "com.android.support:appcompat-v7")
+            .unwrap();
+
+        assert_eq!(
+            mapping.replacement_with_version(),
+            "androidx.appcompat:appcompat:1.1.0"
+        )
+    }
+
+    #[test]
+    fn built_in_replacements_are_androidx() {
+        let mappings = SUPPORT_MAPPINGS
+            .iter()
+            .chain(DATABIND_MAPPINGS.iter())
+            .chain(ARCH_MAPPINGS.iter())
+            .chain(KOTLINX_MAPPINGS.iter())
+            .map(|mapping| (mapping.pattern.as_str(), mapping.replacement.as_str()))
+            .chain(
+                ARTIFACT_MAPPINGS
+                    .iter()
+                    .map(|mapping| (mapping.pattern.as_str(), mapping.replacement.as_str())),
+            );
+
+        assert_eq!(invalid_replacements(mappings), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn non_androidx_replacement_is_invalid() {
+        let mappings = vec![
+            ("android.support.Foo", "androidx.foo.Foo"),
+            ("android.support.Bar", "com.google.android.material.bar.Bar"),
+            ("android.support.Baz", "com.example.Baz"),
+            ("android.support.Kept", "android.support.Kept"),
+        ];
+
+        assert_eq!(
+            invalid_replacements(mappings.into_iter()),
+            vec!["com.example.Baz"]
+        );
+    }
+
+    #[test]
+    fn built_in_mappings_are_valid() {
+        assert_eq!(validate_mappings(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn duplicate_pattern_is_invalid() {
+        let csv = "Support Library class,Android X class
+android.support.Foo,androidx.Foo
+android.support.Foo,androidx.Bar
+";
+        assert_eq!(
+            validate_mapping_csv("test.csv", csv),
+            vec!["test.csv:3: duplicate pattern android.support.Foo"]
+        );
+    }
+
+    #[test]
+    fn unreachable_pattern_is_invalid() {
+        let csv = "Support Library class,Android X class
+android.support.Foo,androidx.Foo
+android.support.Fo.,androidx.Bar
+";
+        assert_eq!(
+            validate_mapping_csv("test.csv", csv),
+            vec![
+                "test.csv:2: pattern android.support.Foo is never reached since \
+                 android.support.Fo. on line 3 matches first"
+            ]
+        );
+    }
+
+    #[test]
+    fn old_package_replacement_is_invalid() {
+        let csv = "Support Library class,Android X class
+android.support.Foo,android.support.Bar
+";
+        assert_eq!(
+            validate_mapping_csv("test.csv", csv),
+            vec!["test.csv:2: replacement android.support.Bar is still an old package"]
+        );
+    }
+
+    #[test]
+    fn invalid_regex_pattern_is_invalid() {
+        let csv = "Support Library class,Android X class
+android.support.Foo(,androidx.Foo
+";
+        let issues = validate_mapping_csv("test.csv", csv);
+
+        assert_eq!(issues.len(), 1);
+        assert!(
+            issues[0].starts_with("test.csv:2: pattern android.support.Foo( is not a valid regex")
+        );
+    }
+
+    #[test]
+    fn mappings_hash_is_stable() {
+        assert_eq!(mappings_hash(), mappings_hash());
+        assert_eq!(mappings_hash().len(), 64);
+    }
+
+    #[test]
+    fn mapping_csv_is_read_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("example.csv"), "from dir").unwrap();
+
+        assert_eq!(
+            mapping_csv(Some(dir.path()), "example.csv", "compiled"),
+            "from dir"
+        );
+    }
+
+    #[test]
+    fn mapping_csv_includes_are_merged() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("extra")).unwrap();
+        fs::write(
+            dir.path().join("example.csv"),
+            "Support Library class,Android X class,Description
+android.support.v4.app.Fragment,androidx.fragment.app.Fragment,Moved to fragment
+$include,extra/custom.csv
+",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("extra/custom.csv"),
+            "Support Library class,Android X class
+android.support.example.Custom,androidx.example.Custom
+",
+        )
+        .unwrap();
+
+        let contents = mapping_csv(Some(dir.path()), "example.csv", "compiled");
+        let mappings: Vec<Mapping> = csv::Reader::from_reader(contents.as_bytes())
+            .deserialize()
+            .map(|result| result.unwrap())
+            .collect();
+
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[1].replacement, "androidx.example.Custom");
+        assert_eq!(mappings[1].description, None);
+    }
+
+    #[test]
+    fn mapping_csv_include_cycle_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.csv");
+        let contents = "Support Library class,Android X class\n$include,b.csv\n";
+        fs::write(&path, contents).unwrap();
+        fs::write(
+            dir.path().join("b.csv"),
+            "Support Library class,Android X class\n$include,a.csv\n",
+        )
+        .unwrap();
+
+        let error = resolve_includes(&path, contents).unwrap_err();
+
+        assert!(error.contains("would be a cycle"), "{}", error);
+    }
+
+    #[test]
+    fn mapping_csv_includes_nested_too_deep_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..=MAX_INCLUDE_DEPTH + 1 {
+            fs::write(
+                dir.path().join(format!("{}.csv", i)),
+                format!(
+                    "Support Library class,Android X class\n$include,{}.csv\n",
+                    i + 1
+                ),
+            )
+            .unwrap();
+        }
+        let path = dir.path().join("0.csv");
+
+        let error = resolve_includes(&path, &fs::read_to_string(&path).unwrap()).unwrap_err();
+
+        assert!(error.contains("nested more than 5 deep"), "{}", error);
+    }
+
+    #[test]
+    fn mapping_csv_missing_from_dir_is_compiled_in_version() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(
+            mapping_csv(Some(dir.path()), "example.csv", "compiled"),
+            "compiled"
+        );
+    }
+
+    #[test]
+    fn mapping_csv_without_dir_is_compiled_in_version() {
+        assert_eq!(mapping_csv(None, "example.csv", "compiled"), "compiled");
+    }
+
     #[test]
     fn support_import_statements_are_matched() {
         let line = "import android.support.animation.DynamicAnimation";
@@ -229,6 +1081,12 @@ mod tests {
         assert!(!SUPPORT_MIN_MATCH.is_match(line))
     }
 
+    #[test]
+    fn support_url_in_comment_not_matched() {
+        let line = "// See https://android.support/ and the android.support docs";
+        assert!(!SUPPORT_MIN_MATCH.is_match(line))
+    }
+
     #[test]
     fn databinding_import_statements_are_matched() {
         let line = "import android.databinding.adapters.AbsListViewBindingAdapter";
@@ -265,6 +1123,24 @@ mod tests {
         assert!(!DATABIND_MIN_MATCH.is_match(line))
     }
 
+    #[test]
+    fn kotlinx_parcelize_annotation_matched() {
+        let line = "@kotlinx.android.parcel.Parcelize";
+        assert!(KOTLINX_MIN_MATCH.is_match(line))
+    }
+
+    #[test]
+    fn kotlinx_synthetic_import_matched() {
+        let line = "import kotlinx.android.synthetic.main.activity_main.toolbar";
+        assert!(KOTLINX_MIN_MATCH.is_match(line))
+    }
+
+    #[test]
+    fn kotlinx_false_positive_not_matched() {
+        let line = "import kotlinx.coroutines.android.HandlerDispatcher";
+        assert!(!KOTLINX_MIN_MATCH.is_match(line))
+    }
+
     #[test]
     fn arch_import_statements_are_matched() {
         let line = "import android.arch.persistence.room.ForeignKey";
@@ -356,11 +1232,23 @@ mod tests {
     }
 
     #[test]
-    fn star_import_does_match_wildcard_proguard_line() {
-        let line = "-dontwarn android.support.design.**";
+    fn star_import_matches_java_static_star_import() {
+        let line = "import static android.support.test.espresso.Espresso.*;";
         assert!(STAR_IMPORT_MATCH.is_match(line))
     }
 
+    #[test]
+    fn star_import_does_not_match_wildcard_proguard_line() {
+        let line = "-dontwarn android.support.design.**";
+        assert!(!STAR_IMPORT_MATCH.is_match(line))
+    }
+
+    #[test]
+    fn star_import_does_not_match_wildcard_proguard_class() {
+        let line = "-keep class android.support.v7.widget.* { *; }";
+        assert!(!STAR_IMPORT_MATCH.is_match(line))
+    }
+
     #[test]
     fn star_import_does_not_match_generics() {
         let line = "): ArrayList<*>";
@@ -1,190 +1,1357 @@
+use crate::error::MigrationError;
 use crate::mappings::{
-    ArtifactMapping, Mapping, ARCH_MAPPINGS, ARCH_MIN_MATCH, ARCH_MIN_MATCH_LEN, ARTIFACT_MAPPINGS,
-    ARTIFACT_MIN_MATCH, ARTIFACT_MIN_MATCH_LEN, DATABIND_MAPPINGS, DATABIND_MIN_MATCH,
-    DATABIND_MIN_MATCH_LEN, STAR_IMPORT_MATCH, SUPPORT_MAPPINGS, SUPPORT_MIN_MATCH,
-    SUPPORT_MIN_MATCH_LEN,
+    ArtifactMapping, Category, Mapping, ARCH_MAPPINGS, ARCH_MIN_MATCH, ARTIFACT_MAPPINGS,
+    ARTIFACT_MIN_MATCH, DATABIND_MAPPINGS, DATABIND_MIN_MATCH, KOTLINX_MAPPINGS, KOTLINX_MIN_MATCH,
+    STAR_IMPORT_MATCH, SUPPORT_MAPPINGS, SUPPORT_MIN_MATCH,
 };
 use crossbeam_channel::{Receiver, Sender};
-use memmap::MmapOptions;
+use encoding_rs::{Encoding, UTF_8};
+use filetime::FileTime;
+use memmap2::{Mmap, MmapOptions};
+use regex::{Regex, RegexSet};
 use tempfile::NamedTempFile;
 
 use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
 use std::fs;
 use std::io::prelude::*;
-use std::io::Result;
-use std::ops::Deref;
-use std::path::PathBuf;
+use std::io::{self, BufReader, BufWriter};
+use std::ops::{Deref, Range};
+use std::path::{Component, Path, PathBuf};
 use std::str;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::vec::Vec;
 
+#[derive(Default)]
 pub struct MatchInfo {
     pub matcher_id: usize,
     pub path: PathBuf,
+    /// Size of the file in bytes
+    pub file_size: u64,
     pub matches_found: usize,
-    pub artifacts_found: Vec<&'static ArtifactMapping>,
+    pub artifacts_found: Vec<ArtifactMatch>,
     pub matched_star_imports: Vec<String>,
+    /// The 1-based line number of each of the matched star imports
+    pub star_import_line_numbers: Vec<usize>,
+    pub unrecognized_patterns: Vec<String>,
+    pub replaced_lines: Vec<ReplacedLine>,
+    /// Number of lines that didn't match any pattern check at all
+    pub unchanged_lines: usize,
+    /// Number of lines in the file
+    pub total_lines: usize,
+    /// Number of lines that passed one of the quick pattern checks, whether or not a mapping was
+    /// found for them
+    pub lines_with_potential_matches: usize,
+    /// Number of times each mapping's pattern was applied, only counted when profiling
+    pub mapping_hits: HashMap<String, usize>,
+    /// The 1-based line number and replacement of each replacement made with an uncertain mapping
+    pub low_confidence_replacements: Vec<(usize, String)>,
+    /// The 1-based line number and deprecation notice of each replacement made with a deprecated
+    /// mapping
+    pub deprecated_replacements: Vec<(usize, String)>,
+    /// The 1-based line number and matching time of each line that took longer than the regex
+    /// timeout to match against the mappings
+    pub slow_lines: Vec<(usize, Duration)>,
+    /// Number of replacements made for each category of mappings
+    pub category_counts: HashMap<Category, usize>,
+    /// Every class the old ones were replaced with, only collected when suggesting a BOM version
+    pub replacement_classes: BTreeSet<String>,
+    pub skipped_too_large: bool,
+    /// The file is a symlink and following symlinks is disabled
+    pub skipped_symlink: bool,
+    /// The file had more replacements than the threshold so nothing was written
+    pub skipped_high_count: bool,
+    /// How long it took to migrate the file, zero if it was skipped
+    pub duration: Duration,
+    /// What would have been written to the file, only set for a dry run
+    pub dry_run_result: Option<DryRunResult>,
+}
+
+/// What a dry run would have done with a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DryRunResult {
+    /// Nothing would have been written since there was nothing to change
+    NoChanges,
+    /// The file would have been written but was left untouched
+    WriteSkipped,
+}
+
+impl MatchInfo {
+    /// How much of the file looked like it referenced an old package, as a rough measure of how
+    /// closely it needs to be reviewed by hand.
+    ///
+    /// Returns the percentage of lines with potential matches, 0 for an empty file
+    pub fn difficulty(&self) -> f64 {
+        if self.total_lines == 0 {
+            0.0
+        } else {
+            self.lines_with_potential_matches as f64 * 100.0 / self.total_lines as f64
+        }
+    }
+
+    /// Create a MatchInfo for a file where nothing was found.
+    ///
+    /// * `matcher_id` - The ID of the matcher that operated on the file
+    /// * `path` - The path of the file
+    /// * `file_size` - The size of the file in bytes
+    fn empty(matcher_id: usize, path: PathBuf, file_size: u64) -> Self {
+        MatchInfo {
+            matcher_id,
+            path,
+            file_size,
+            ..Default::default()
+        }
+    }
+
+    /// Everything found in the file that has to be looked at by hand, in the order it's printed.
+    ///
+    /// Returns a section per kind of problem found, empty kinds are left out
+    pub fn warnings(&self) -> Vec<Warning> {
+        let path = self.path.to_string_lossy();
+        let mut warnings = Vec::new();
+        let mut push = |kind, heading: String, items: Vec<String>| {
+            if !items.is_empty() {
+                warnings.push(Warning {
+                    kind,
+                    heading,
+                    items,
+                })
+            }
+        };
+
+        push(
+            WarningKind::StarImports,
+            format!(
+                "Found {} star import(s) that must be updated in {}:",
+                self.matched_star_imports.len(),
+                path
+            ),
+            self.matched_star_imports.clone(),
+        );
+        push(
+            WarningKind::Unrecognized,
+            format!(
+                "Found {} unrecognized support library reference(s) in {}:",
+                self.unrecognized_patterns.len(),
+                path
+            ),
+            self.unrecognized_patterns.clone(),
+        );
+        push(
+            WarningKind::Uncertain,
+            format!(
+                "Found {} uncertain replacement(s) that should be checked in {}:",
+                self.low_confidence_replacements.len(),
+                path
+            ),
+            self.low_confidence_replacements
+                .iter()
+                .map(|(line_number, replacement)| format!("line {}: {}?", line_number, replacement))
+                .collect(),
+        );
+        push(
+            WarningKind::Deprecated,
+            format!(
+                "Found {} deprecated replacement(s) in {}:",
+                self.deprecated_replacements.len(),
+                path
+            ),
+            self.deprecated_replacements
+                .iter()
+                .map(|(line_number, notice)| format!("line {}: {}", line_number, notice))
+                .collect(),
+        );
+        push(
+            WarningKind::SlowLines,
+            format!(
+                "Found {} line(s) over the regex timeout in {}:",
+                self.slow_lines.len(),
+                path
+            ),
+            self.slow_lines
+                .iter()
+                .map(|(line_number, elapsed)| {
+                    format!("line {}: {}ms", line_number, elapsed.as_millis())
+                })
+                .collect(),
+        );
+        push(
+            WarningKind::Artifacts,
+            format!(
+                "Found {} artifact(s) that must be updated in {}:",
+                self.artifacts_found.len(),
+                path
+            ),
+            self.artifacts_found
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        );
+        warnings
+    }
+}
+
+impl fmt::Display for MatchInfo {
+    /// Formats everything found in the file the way it's printed when migrating, without colour.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} replacement(s)",
+            self.path.to_string_lossy(),
+            self.matches_found
+        )?;
+        for warning in self.warnings() {
+            write!(f, "\n{}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// The kinds of problems found in a file that have to be looked at by hand
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WarningKind {
+    /// Star imports of an old package
+    StarImports,
+    /// References to an old package without a mapping
+    Unrecognized,
+    /// Replacements made with an uncertain mapping
+    Uncertain,
+    /// Replacements whose AndroidX class has since moved again
+    Deprecated,
+    /// Lines that took longer than the regex timeout to match
+    SlowLines,
+    /// Old build artifacts
+    Artifacts,
+}
+
+/// A single kind of problem found in a file, printed as a heading followed by a bullet per item
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub heading: String,
+    pub items: Vec<String>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.heading)?;
+        for item in &self.items {
+            write!(f, "\n  * {}", item)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single line that had a replacement made in it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplacedLine {
+    /// The 1-based line number within the file
+    pub line_number: usize,
+    pub old: String,
+    pub new: String,
+    /// The description of the mapping that was applied, if it has one
+    pub description: Option<String>,
+}
+
+/// A mapping that matches a line, from [`Matcher::explain`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    /// The pattern of the mapping
+    pub pattern: String,
+    /// What the pattern is replaced with
+    pub replacement: String,
+    /// The category of the mapping
+    pub category: Category,
+    /// The byte range of the match within the line
+    pub range: Range<usize>,
+    /// Whether this is the mapping that's actually applied to the line. Only the first matching
+    /// mapping is applied, and only if the line passes the quick checks for its category.
+    pub applied: bool,
+}
+
+/// An old artifact that was found in a build file
+#[derive(Debug)]
+pub struct ArtifactMatch {
+    pub mapping: ArtifactMapping,
+    /// The 1-based line number the artifact was found on
+    pub line_number: usize,
+    /// The version exactly as it was declared, e.g. `28.0.0` or `${versions.support}`
+    pub version: Option<String>,
+}
+
+impl fmt::Display for ArtifactMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The longest artifact is 59 characters so pad for that
+        write!(
+            f,
+            "{:<60}=> {}",
+            self.mapping.pattern.as_str(),
+            self.mapping.replacement_with_version()
+        )?;
+        if let Some(version) = &self.version {
+            write!(f, " (currently {})", version)?;
+        }
+        Ok(())
+    }
+}
+
+/// What was found on a single line of source code
+#[derive(Debug)]
+enum LineMatch<'m> {
+    /// Nothing that needs migrating was found
+    None,
+    /// The given mappings of the given categories were found and applied to the line in order
+    Replaced(Vec<(&'m Mapping, Category)>),
+    /// A star import was found, which can't be replaced
+    StarImport,
+    /// The line looks like it references the support library but no mapping matched
+    Unrecognized,
+}
+
+// Track how many bytes are memory mapped across all the matchers at once, and the most that have
+// ever been mapped at the same time, to help profile memory usage on large projects.
+static MAPPED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_MAPPED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The most bytes that have been memory mapped at the same time across all matchers.
+pub fn peak_mapped_bytes() -> usize {
+    PEAK_MAPPED_BYTES.load(Ordering::Relaxed)
+}
+
+/// Counts a memory mapped file towards the number of mapped bytes for as long as it's alive.
+struct MappedBytes(usize);
+
+impl MappedBytes {
+    fn track(len: usize) -> Self {
+        let mapped = MAPPED_BYTES.fetch_add(len, Ordering::Relaxed) + len;
+        PEAK_MAPPED_BYTES.fetch_max(mapped, Ordering::Relaxed);
+        MappedBytes(len)
+    }
+}
+
+impl Drop for MappedBytes {
+    fn drop(&mut self) {
+        MAPPED_BYTES.fetch_sub(self.0, Ordering::Relaxed);
+    }
+}
+
+/// How many path components deep build artifacts are looked for by default, enough for the top
+/// level build files and the ones in each module
+pub const DEFAULT_ARTIFACT_DEPTH: usize = 2;
+
+/// Options shared by all matchers that change how files are operated on.
+#[derive(Clone, Debug, Default)]
+pub struct MatcherOptions {
+    /// Files larger than this number of bytes are skipped entirely
+    pub max_file_size: Option<u64>,
+    /// Find matches without writing any changes back to disk
+    pub dry_run: bool,
+    /// Log extra information about each file as it's operated on
+    pub debug: bool,
+    /// Skip symlinked files instead of migrating them
+    pub no_follow_symlinks: bool,
+    /// Only look for old build artifacts without migrating anything
+    pub only_artifacts: bool,
+    /// Read files in batches through io_uring when it's available
+    #[cfg_attr(not(all(target_os = "linux", feature = "io-uring")), allow(dead_code))]
+    pub io_uring: bool,
+    /// Encoding of the files, UTF-8 if not given
+    pub encoding: Option<&'static Encoding>,
+    /// Count how many times each mapping is applied
+    pub profile: bool,
+    /// Keep the original modification time of migrated files
+    pub preserve_timestamps: bool,
+    /// Read files line by line instead of memory mapping them
+    pub no_mmap: bool,
+    /// Stop writing changes once this many replacements have been made across all files
+    pub max_replacements: Option<usize>,
+    /// The number of replacements written so far, shared by every matcher
+    pub total_replacements: Arc<AtomicUsize>,
+    /// Files with more replacements than this are left untouched, they're likely generated
+    pub replacement_count_threshold: Option<usize>,
+    /// Write changes straight into the original file instead of persisting a temp file over it
+    pub no_tempfile: bool,
+    /// Write migrated files to the same relative path in this directory instead of in place
+    pub output_dir: Option<PathBuf>,
+    /// Set once the run is aborted, any files received after that are skipped
+    pub aborted: Arc<AtomicBool>,
+    /// How many directories deep build artifacts are looked for, [`DEFAULT_ARTIFACT_DEPTH`] if
+    /// not given
+    pub artifact_depth: Option<usize>,
+    /// Never look for build artifacts
+    pub no_artifact_check: bool,
+    /// Look for build artifacts in build files at any depth
+    pub artifact_check_all: bool,
+    /// Directory the depth of build files is counted from instead of the root of the project
+    pub subdir: Option<PathBuf>,
+    /// Lines matching any of these are left untouched
+    pub exclude_patterns: RegexSet,
+    /// Lines shorter than this are never checked against the class mappings, instead of the
+    /// length of the shortest pattern
+    pub min_pattern_length: Option<usize>,
+    /// Collect every class the old ones are replaced with
+    pub suggest_bom_version: bool,
+    /// Report lines that take longer than this to match against the mappings
+    pub regex_timeout: Option<Duration>,
+}
+
+/// The most lines a single wrapped build file line is joined from
+const MAX_CONTINUED_LINES: usize = 10;
+
+/// Where the migrated lines of a file are written to
+enum Output {
+    /// Lines are kept in memory until it's known whether the file changes at all
+    Buffered(Vec<u8>),
+    /// Lines are streamed to a temp file as they're migrated
+    Streamed(BufWriter<NamedTempFile>),
+}
+
+impl Output {
+    /// Start streaming lines to a new temp file, writing out every line buffered so far first.
+    /// Nothing is done if lines are already being streamed.
+    ///
+    /// * `dir` - The directory to create the temp file in
+    fn stream_to(&mut self, dir: &Path) -> io::Result<()> {
+        if let Output::Buffered(buffer) = self {
+            let mut writer = BufWriter::new(NamedTempFile::new_in(dir)?);
+            writer.write_all(buffer)?;
+            *self = Output::Streamed(writer);
+        }
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Buffered(buffer) => buffer.write(buf),
+            Output::Streamed(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Buffered(buffer) => buffer.flush(),
+            Output::Streamed(writer) => writer.flush(),
+        }
+    }
+}
+
+/// The full contents of a file
+enum Contents {
+    /// The file is memory mapped
+    Mapped(Mmap),
+    /// The file was already read into memory
+    Read(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Mapped(mmap) => mmap,
+            Contents::Read(bytes) => bytes,
+        }
+    }
 }
 
 pub struct Matcher {
     id: usize,
-    tx: Sender<Result<MatchInfo>>,
+    tx: Sender<Result<MatchInfo, MigrationError>>,
+    options: MatcherOptions,
+    support: Vec<Mapping>,
+    arch: Vec<Mapping>,
+    databind: Vec<Mapping>,
+    kotlinx: Vec<Mapping>,
+    artifacts: Vec<ArtifactMapping>,
+    // The length of the shortest pattern in each of the mappings above
+    support_min_len: usize,
+    arch_min_len: usize,
+    databind_min_len: usize,
+    kotlinx_min_len: usize,
+    artifacts_min_len: usize,
 }
 
 impl Matcher {
-    /// Create a Matcher
+    /// Create a Matcher with the built in mappings
+    ///
+    /// * `id` - The thread number of the matcher
+    /// * `tx` - The transmitter to send information with
+    /// * `options` - The options to operate on files with
+    pub fn new(
+        id: usize,
+        tx: Sender<Result<MatchInfo, MigrationError>>,
+        options: MatcherOptions,
+    ) -> Self {
+        Matcher::new_with_mappings(
+            id,
+            tx,
+            options,
+            SUPPORT_MAPPINGS.to_vec(),
+            ARCH_MAPPINGS.to_vec(),
+            DATABIND_MAPPINGS.to_vec(),
+            KOTLINX_MAPPINGS.to_vec(),
+            ARTIFACT_MAPPINGS.to_vec(),
+        )
+    }
+
+    /// Create a Matcher with custom mappings
     ///
     /// * `id` - The thread number of the matcher
     /// * `tx` - The transmitter to send information with
-    pub fn new(id: usize, tx: Sender<Result<MatchInfo>>) -> Self {
-        Matcher { id, tx }
+    /// * `options` - The options to operate on files with
+    /// * `support` - Mappings for android.support classes
+    /// * `arch` - Mappings for android.arch classes
+    /// * `databind` - Mappings for android.databinding classes
+    /// * `kotlinx` - Mappings for kotlin-android-extensions classes
+    /// * `artifacts` - Mappings for old build artifacts
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_mappings(
+        id: usize,
+        tx: Sender<Result<MatchInfo, MigrationError>>,
+        options: MatcherOptions,
+        mut support: Vec<Mapping>,
+        mut arch: Vec<Mapping>,
+        mut databind: Vec<Mapping>,
+        mut kotlinx: Vec<Mapping>,
+        mut artifacts: Vec<ArtifactMapping>,
+    ) -> Self {
+        // The first matching pattern wins so make sure the longest ones are checked first, the
+        // same as the built in mappings.
+        support.sort_unstable_by_key(|mapping| Reverse(mapping.pattern.as_str().len()));
+        arch.sort_unstable_by_key(|mapping| Reverse(mapping.pattern.as_str().len()));
+        databind.sort_unstable_by_key(|mapping| Reverse(mapping.pattern.as_str().len()));
+        kotlinx.sort_unstable_by_key(|mapping| Reverse(mapping.pattern.as_str().len()));
+        artifacts.sort_unstable_by_key(|mapping| Reverse(mapping.pattern.as_str().len()));
+
+        let min_len = |mappings: &[Mapping]| {
+            options
+                .min_pattern_length
+                .unwrap_or_else(|| min_pattern_len(mappings.iter().map(|m| &m.pattern)))
+        };
+        Matcher {
+            id,
+            tx,
+            support_min_len: min_len(&support),
+            arch_min_len: min_len(&arch),
+            databind_min_len: min_len(&databind),
+            kotlinx_min_len: min_len(&kotlinx),
+            options,
+            artifacts_min_len: min_pattern_len(artifacts.iter().map(|m| &m.pattern)),
+            support,
+            arch,
+            databind,
+            kotlinx,
+            artifacts,
+        }
     }
 
     /// Start the matcher.
     ///
     /// The matcher will wait on receiving a file to operate on from the given receiver, and will
-    /// then finish once the receiver channel signals it is both empty and disconnected.
+    /// then finish once the receiver channel signals it is both empty and disconnected, or once
+    /// the run is aborted before the next file is started.
     /// Information on completion of checking a file will be sent via the Matcher's transmitter.
     ///
     /// * `rx` - The receiver to listen to for files
     pub fn run(self, rx: Receiver<PathBuf>) {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if self.options.io_uring {
+            match crate::uring::BatchReader::new(self.options.max_file_size) {
+                Ok(reader) => return self.run_batched(reader, rx),
+                Err(e) if self.options.debug => {
+                    eprintln!(
+                        "Failed to set up io_uring, memory mapping files instead: {}",
+                        e
+                    )
+                }
+                Err(_) => {}
+            }
+        }
+
         while let Ok(path) = rx.recv() {
+            if self.options.aborted.load(Ordering::SeqCst) {
+                break;
+            }
+            self.process(path);
+        }
+    }
+
+    /// Find and replace everything in a single file and report the result back. Nothing is done
+    /// once the run has been aborted so the remaining files are drained quickly.
+    ///
+    /// * `path` - The path of the file to migrate
+    pub fn process(&self, path: PathBuf) {
+        if !self.options.aborted.load(Ordering::SeqCst) {
             let _ = self.tx.send(self.search_and_replace(path));
         }
     }
 
+    /// Same as [`Matcher::run`], but any files already waiting on the receiver are read together
+    /// in a single batch through io_uring.
+    ///
+    /// * `reader` - The reader to read each batch of files with
+    /// * `rx` - The receiver to get file paths from
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn run_batched(self, mut reader: crate::uring::BatchReader, rx: Receiver<PathBuf>) {
+        while let Ok(path) = rx.recv() {
+            if self.options.aborted.load(Ordering::SeqCst) {
+                break;
+            }
+            let mut paths = vec![path];
+            paths.extend(rx.try_iter().take(crate::uring::BATCH_SIZE - 1));
+            let contents = reader.read_all(&paths);
+            for (path, contents) in paths.into_iter().zip(contents) {
+                let _ = self.tx.send(self.search_and_replace_with(path, contents));
+            }
+        }
+    }
+
     /// Find and replace all occurrences of androidx migrated name spaces within the given file.
     ///
     /// The given file path will be opened as a memory mapped file to improve performance given
-    /// that most source code files are less than 1 MB. Each line is then scanned for any of the
-    /// migrated package names and updated to the new androidx package name.
+    /// that most source code files are less than 1 MB. If the file can't be memory mapped it is
+    /// read line by line through a buffered reader instead. Each line is then scanned for any of
+    /// the migrated package names and updated to the new androidx package name.
     ///
-    /// All updates to the file are first done in memory for performance and in the extremely
-    /// unlikely chance that another program is also accessing the file while we are modifying it.
-    /// If there are changes that need to be made to the file, the new contents are written to a
-    /// temporary file which is then persisted to disk and overwrites the original file with the
-    /// same attributes.
+    /// The original file is never written to directly in the extremely unlikely chance that
+    /// another program is also accessing the file while we are modifying it. Lines are kept in
+    /// memory until the first replacement is found, after which they are streamed to a temporary
+    /// file so large files don't have to be held in memory twice. Once finished the temporary file
+    /// is persisted to disk and overwrites the original file with the same attributes.
     ///
     /// * `path` - The file path to operate on
+    ///
     /// Returns a MatchInfo with information about any matches in the line if successful
-    fn search_and_replace(&self, path: PathBuf) -> Result<MatchInfo> {
-        let file = fs::File::open(&path)?;
-        let mmap = unsafe { MmapOptions::new().map(&file)? };
-        let source = mmap.deref();
+    fn search_and_replace(&self, path: PathBuf) -> Result<MatchInfo, MigrationError> {
+        self.search_and_replace_with(path, None)
+    }
 
-        // To make sure not too much performance is lost finding artifacts assume that artifacts
-        // can only be located in the buildSrc directory, a top level file in the project or one
-        // level down for module's build files.
-        let check_artifact = path.extension().map_or(false, |x| x != "xml" && x != "pro")
-            && (path.starts_with("buildSrc") || path.iter().count() <= 2);
+    /// Same as [`Matcher::search_and_replace`], but with the option of passing in the contents of
+    /// the file if they were already read in.
+    ///
+    /// * `path` - The file path to operate on
+    /// * `contents` - The full contents of the file, if already read
+    ///
+    /// Returns a MatchInfo with information about any matches in the line if successful
+    fn search_and_replace_with(
+        &self,
+        path: PathBuf,
+        contents: Option<Vec<u8>>,
+    ) -> Result<MatchInfo, MigrationError> {
+        let start = Instant::now();
+        if self.options.no_follow_symlinks {
+            let metadata =
+                fs::symlink_metadata(&path).map_err(|e| MigrationError::Open(path.clone(), e))?;
+            if metadata.file_type().is_symlink() {
+                return Ok(MatchInfo {
+                    skipped_symlink: true,
+                    ..MatchInfo::empty(self.id, path, 0)
+                });
+            }
+        }
+
+        let file = fs::File::open(&path).map_err(|e| MigrationError::Open(path.clone(), e))?;
+        let file_size = file
+            .metadata()
+            .map_err(|e| MigrationError::Open(path.clone(), e))?
+            .len();
+
+        // Bail out before mapping the file if it's too big to be worth processing, these are
+        // almost always generated files anyways.
+        if let Some(max_file_size) = self.options.max_file_size {
+            if file_size > max_file_size {
+                return Ok(MatchInfo {
+                    skipped_too_large: true,
+                    ..MatchInfo::empty(self.id, path, file_size)
+                });
+            }
+        }
+
+        let check_artifact = self.may_declare_artifacts(&path);
+        if self.options.only_artifacts && !check_artifact {
+            return Ok(MatchInfo::empty(self.id, path, file_size));
+        }
+
+        // Safety: memmap2 can't guarantee the underlying file isn't modified or truncated by
+        // another process while it's mapped, which would be undefined behavior. Files are only
+        // ever replaced by persisting a new temp file over them so the mapped inode itself is
+        // never written to by this tool, except with `no_tempfile` where the mapping is dropped
+        // before the file is truncated.
+        let contents = match contents {
+            Some(bytes) => Ok(Contents::Read(bytes)),
+            None if self.options.no_mmap => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "memory mapping is disabled",
+            )),
+            None => unsafe { MmapOptions::new().map(&file) }.map(Contents::Mapped),
+        };
+        // Files in any other encoding are decoded all at once, so they need to be read in fully
+        // when they can't be mapped.
+        let encoding = self.options.encoding.filter(|&encoding| encoding != UTF_8);
+        let contents = match contents {
+            Err(_) if encoding.is_some() => {
+                let mut bytes = Vec::with_capacity(file_size as usize);
+                (&file)
+                    .read_to_end(&mut bytes)
+                    .map_err(|e| MigrationError::read(&path, e))?;
+                Ok(Contents::Read(bytes))
+            }
+            contents => contents,
+        };
+        let _mapped = match &contents {
+            Ok(Contents::Mapped(mmap)) => Some(MappedBytes::track(mmap.len())),
+            _ => None,
+        };
+        if self.options.debug {
+            match &contents {
+                Ok(Contents::Mapped(mmap)) => {
+                    eprintln!("Mapped {} ({} bytes)", path.to_string_lossy(), mmap.len())
+                }
+                Ok(Contents::Read(bytes)) => {
+                    eprintln!("Read {} ({} bytes)", path.to_string_lossy(), bytes.len())
+                }
+                Err(e) => eprintln!("Failed to map {}: {}", path.to_string_lossy(), e),
+            }
+        }
 
-        // Create a simple "buffer" to write to as we change lines
-        let mut output = Vec::with_capacity(mmap.len());
+        // Files in any other encoding are converted to UTF-8 up front so they can be matched the
+        // same way as every other file.
+        let decoded = match (encoding, &contents) {
+            (Some(encoding), Ok(contents)) => {
+                Some(encoding.decode_without_bom_handling(contents).0)
+            }
+            _ => None,
+        };
+
+        // Memory mapping isn't supported on every filesystem, e.g. NFS or some Docker volume
+        // mounts, so fall back to reading the file line by line when it fails.
+        let (lines, crlf): (Box<dyn Iterator<Item = io::Result<Cow<str>>>>, bool) =
+            match (&decoded, &contents) {
+                (Some(decoded), _) => (
+                    Box::new(decoded.lines().map(|line| Ok(Cow::Borrowed(line)))),
+                    uses_crlf(decoded.as_bytes()),
+                ),
+                (None, Ok(contents)) => (
+                    Box::new(
+                        str::from_utf8(contents.deref())
+                            .map_err(|_| MigrationError::Decode(path.clone()))?
+                            .lines()
+                            .map(|line| Ok(Cow::Borrowed(line))),
+                    ),
+                    uses_crlf(contents),
+                ),
+                (None, Err(_)) => {
+                    let mut reader = BufReader::new(&file);
+                    let crlf = uses_crlf(
+                        reader
+                            .fill_buf()
+                            .map_err(|e| MigrationError::read(&path, e))?,
+                    );
+                    (
+                        Box::new(reader.lines().map(|line| line.map(Cow::Owned))),
+                        crlf,
+                    )
+                }
+            };
+        // Both ways of reading lines strip the line endings so make sure to write back whichever
+        // style the file was originally using.
+        let line_ending = if crlf { "\r\n" } else { "\n" };
+
+        // Lines can only be streamed to a temp file when they're written out as is, otherwise the
+        // whole file is buffered and written at the end
+        let stream = !self.options.dry_run && !self.options.no_tempfile && encoding.is_none();
+        let mut output = Output::Buffered(Vec::with_capacity(if stream {
+            0
+        } else {
+            file_size as usize
+        }));
         let mut replacements = 0;
-        let mut artifacts: Vec<&'static ArtifactMapping> = Vec::new();
+        let mut artifacts: Vec<ArtifactMatch> = Vec::new();
         let mut star_imports: Vec<String> = Vec::new();
-        for line in str::from_utf8(source).unwrap().lines() {
-            let (line_to_write, found_match, found_star_import) = self.find_match(&line);
+        let mut star_import_line_numbers: Vec<usize> = Vec::new();
+        let mut unrecognized: Vec<String> = Vec::new();
+        let mut replaced_lines: Vec<ReplacedLine> = Vec::new();
+        let mut mapping_hits: HashMap<String, usize> = HashMap::new();
+        let mut category_counts: HashMap<Category, usize> = HashMap::new();
+        let mut replacement_classes = BTreeSet::new();
+        let mut low_confidence_replacements = Vec::new();
+        let mut deprecated_replacements = Vec::new();
+        let mut slow_lines = Vec::new();
+        let mut unchanged_lines = 0;
+        let mut total_lines = 0;
+        let mut lines_with_potential_matches = 0;
+        let mut joiner = LineJoiner::default();
+        for (i, line) in lines.enumerate() {
+            let line = line.map_err(|e| MigrationError::read(&path, e))?;
+            total_lines += 1;
+            // Matches can't be interrupted, so only time them when asked to
+            let match_start = self.options.regex_timeout.map(|_| Instant::now());
+            let (line_to_write, line_match) = if self.options.only_artifacts {
+                (Cow::Borrowed(line.as_ref()), LineMatch::None)
+            } else {
+                self.find_match(&line)
+            };
+            if let (Some(timeout), Some(match_start)) = (self.options.regex_timeout, match_start) {
+                let elapsed = match_start.elapsed();
+                if elapsed > timeout {
+                    slow_lines.push((i + 1, elapsed));
+                }
+            }
+            if !matches!(line_match, LineMatch::None) {
+                lines_with_potential_matches += 1;
+            }
 
-            if found_match {
+            match line_match {
                 // Count the number of replacements we've made
-                replacements += 1;
-            } else if found_star_import {
-                star_imports.push(String::from(line));
-            } else if check_artifact {
-                // Only check for artifacts if nothing else matches since it's almost impossible an
-                // artifact declaration would be on the same line as a package.
-                if let Some(artifact) = self.find_artifact_match(&line) {
-                    artifacts.push(artifact);
+                LineMatch::Replaced(applied) => {
+                    replacements += applied.len();
+                    replaced_lines.push(ReplacedLine {
+                        line_number: i + 1,
+                        old: String::from(line.as_ref()),
+                        new: String::from(line_to_write.as_ref()),
+                        description: applied
+                            .iter()
+                            .find_map(|(mapping, _)| mapping.description.clone()),
+                    });
+                    for (mapping, category) in applied {
+                        *category_counts.entry(category).or_default() += 1;
+                        if mapping.is_uncertain() {
+                            low_confidence_replacements.push((i + 1, mapping.replacement.clone()));
+                        }
+                        if let Some(deprecated) = &mapping.deprecated {
+                            deprecated_replacements.push((i + 1, deprecated.clone()));
+                        }
+                        if self.options.suggest_bom_version {
+                            replacement_classes.insert(mapping.replacement.clone());
+                        }
+                        if self.options.profile {
+                            *mapping_hits
+                                .entry(String::from(mapping.pattern.as_str()))
+                                .or_default() += 1;
+                        }
+                    }
+                }
+                LineMatch::StarImport => {
+                    star_imports.push(String::from(line.as_ref()));
+                    star_import_line_numbers.push(i + 1);
                 }
+                LineMatch::Unrecognized => unrecognized.push(String::from(line.as_ref())),
+                LineMatch::None => {
+                    unchanged_lines += 1;
+                    // Only check for artifacts if nothing else matches since it's almost
+                    // impossible an artifact declaration would be on the same line as a package.
+                    // Artifacts are only ever reported, so the lines are still written back
+                    // exactly as they were split up.
+                    if check_artifact {
+                        if let Some(joined) = joiner.push(&line, i + 1) {
+                            artifacts.extend(self.find_artifact_matches(&joined));
+                        }
+                    }
+                }
+            }
+            // Nothing is written to disk until there's something to change, then every line up to
+            // now is written out first
+            if stream && replacements > 0 && matches!(output, Output::Buffered(_)) {
+                let write_error = |e| MigrationError::Write(path.clone(), e);
+                let target = self.write_target(&path).map_err(write_error)?;
+                output
+                    .stream_to(target.parent().unwrap_or(&target))
+                    .map_err(write_error)?;
             }
-            // Write out to the buffer
-            writeln!(output, "{}", &line_to_write)?;
+            write!(output, "{}{}", &line_to_write, line_ending)
+                .map_err(|e| MigrationError::Write(path.clone(), e))?;
+        }
+        if let Some(joined) = joiner.finish() {
+            artifacts.extend(self.find_artifact_matches(&joined));
         }
 
-        // Make sure to only create the temp file if anything actually changed
-        if replacements > 0 {
-            let mut tempfile = NamedTempFile::new_in(&path.parent().unwrap_or(&path))?;
+        // Make sure to only create the temp file if anything actually changed. Files with an
+        // unusually high number of replacements are often generated and better left for a person
+        // to look at.
+        let skipped_high_count = self
+            .options
+            .replacement_count_threshold
+            .is_some_and(|threshold| replacements > threshold);
+        if replacements > 0 && !self.options.dry_run && !skipped_high_count {
+            // Keep a running total across all the matchers so a bad mapping can't rewrite the whole
+            // project before anyone notices.
+            if let Some(max_replacements) = self.options.max_replacements {
+                let total = self
+                    .options
+                    .total_replacements
+                    .fetch_add(replacements, Ordering::SeqCst)
+                    + replacements;
+                if total > max_replacements {
+                    return Err(MigrationError::TooManyReplacements(path));
+                }
+            }
 
-            // Write out the changes to disk
-            tempfile.write_all(&output)?;
-            tempfile.flush()?;
+            let write_error = |e| MigrationError::Write(path.clone(), e);
+            let persist_error = |e| MigrationError::Persist(path.clone(), e);
+            let target = self.write_target(&path).map_err(write_error)?;
+
+            // Symlinks are followed for the permissions, but the file is always persisted over the
+            // link itself so nothing outside the repo is ever modified.
+            let metadata = fs::metadata(&path).map_err(persist_error)?;
+            let is_symlink = fs::symlink_metadata(&path)
+                .map_err(persist_error)?
+                .file_type()
+                .is_symlink();
+
+            let tempfile = match output {
+                Output::Streamed(writer) => Some(
+                    writer
+                        .into_inner()
+                        .map_err(|e| write_error(e.into_error()))?,
+                ),
+                Output::Buffered(output) => {
+                    // Write out the changes to disk in the file's original encoding. Every line
+                    // was written to the buffer as a str so it's always valid UTF-8.
+                    let output = match encoding {
+                        Some(encoding) => encoding.encode(str::from_utf8(&output).unwrap()).0,
+                        None => Cow::Borrowed(output.as_slice()),
+                    };
+
+                    if self.options.no_tempfile && !is_symlink {
+                        // The original file can't still be mapped while it's truncated
+                        drop(decoded);
+                        drop(contents);
+                        let mut file = fs::OpenOptions::new()
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .open(&target)
+                            .map_err(write_error)?;
+                        file.write_all(&output).map_err(write_error)?;
+                        file.flush().map_err(write_error)?;
+                        if target != path {
+                            fs::set_permissions(&target, metadata.permissions())
+                                .map_err(persist_error)?;
+                        }
+                        None
+                    } else {
+                        let mut tempfile =
+                            NamedTempFile::new_in(target.parent().unwrap_or(&target))
+                                .map_err(write_error)?;
+                        tempfile.write_all(&output).map_err(write_error)?;
+                        tempfile.flush().map_err(write_error)?;
+                        Some(tempfile)
+                    }
+                }
+            };
+
+            // Persist the tempfile and override the original, or write it to the output directory.
+            if let Some(tempfile) = tempfile {
+                fs::set_permissions(tempfile.path(), metadata.permissions())
+                    .map_err(persist_error)?;
+                tempfile
+                    .persist(&target)
+                    .map_err(|e| persist_error(e.error))?;
+            }
 
-            // Persist the tempfile and override the original
-            let real_path = fs::canonicalize(&path)?;
-            let metadata = fs::metadata(&real_path)?;
-            fs::set_permissions(tempfile.path(), metadata.permissions())?;
-            tempfile.persist(&real_path)?;
+            // Persisting creates a new file so the modification time has to be restored after
+            if self.options.preserve_timestamps {
+                let mtime = FileTime::from_last_modification_time(&metadata);
+                filetime::set_file_mtime(&target, mtime).map_err(persist_error)?;
+            }
         }
 
         Ok(MatchInfo {
             matcher_id: self.id,
             path,
+            file_size,
             matches_found: replacements,
             artifacts_found: artifacts,
             matched_star_imports: star_imports,
+            star_import_line_numbers,
+            unrecognized_patterns: unrecognized,
+            replaced_lines,
+            unchanged_lines,
+            total_lines,
+            lines_with_potential_matches,
+            mapping_hits,
+            dry_run_result: self.options.dry_run.then_some(
+                if replacements > 0 && !skipped_high_count {
+                    DryRunResult::WriteSkipped
+                } else {
+                    DryRunResult::NoChanges
+                },
+            ),
+            low_confidence_replacements,
+            deprecated_replacements,
+            slow_lines,
+            category_counts,
+            replacement_classes,
+            skipped_too_large: false,
+            skipped_symlink: false,
+            skipped_high_count,
+            duration: start.elapsed(),
         })
     }
 
-    /// Given a line of code, return the potentially new line with androidx package names, if that
-    /// replacement occurred, and if a star import that matched was found (which is not replacable).
+    /// Where a migrated file is written to, either over the original or at the same relative path
+    /// in the output directory. Directories leading up to it in the output directory are created.
     ///
-    /// * `line` - The source code line
-    fn find_match<'a>(&self, line: &'a str) -> (Cow<'a, str>, bool, bool) {
-        // Do some simple heuristics to make sure it even worth checking the full set of patterns
-        if line.trim().len() >= *SUPPORT_MIN_MATCH_LEN && SUPPORT_MIN_MATCH.is_match(line) {
-            self.match_line_with_patterns(line, &*SUPPORT_MAPPINGS)
-        } else if line.trim().len() >= *ARCH_MIN_MATCH_LEN && ARCH_MIN_MATCH.is_match(line) {
-            self.match_line_with_patterns(line, &*ARCH_MAPPINGS)
-        } else if line.trim().len() >= *DATABIND_MIN_MATCH_LEN && DATABIND_MIN_MATCH.is_match(line)
-        {
-            self.match_line_with_patterns(line, &*DATABIND_MAPPINGS)
-        } else {
-            (Cow::Borrowed(line), false, false)
+    /// * `path` - The path of the file being migrated
+    ///
+    /// Returns the path to write the migrated file to
+    fn write_target(&self, path: &Path) -> io::Result<PathBuf> {
+        match &self.options.output_dir {
+            Some(output_dir) => {
+                let target = output_path(output_dir, path);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                Ok(target)
+            }
+            None => Ok(path.to_path_buf()),
+        }
+    }
+
+    /// Whether the file at the given path could declare any build artifacts.
+    ///
+    /// * `path` - The path of the file relative to the root of the project
+    fn may_declare_artifacts(&self, path: &Path) -> bool {
+        // To make sure not too much performance is lost finding artifacts assume that artifacts
+        // can only be located in the buildSrc or build-logic directories, a top level file in the
+        // project or one level down for module's build files unless told to look deeper.
+        if self.options.no_artifact_check {
+            return false;
         }
+        let depth = self
+            .options
+            .artifact_depth
+            .unwrap_or(DEFAULT_ARTIFACT_DEPTH);
+        let path = self
+            .options
+            .subdir
+            .as_ref()
+            .and_then(|dir| path.strip_prefix(dir).ok())
+            .unwrap_or(path);
+        path.extension().is_some_and(|x| x != "xml" && x != "pro")
+            && (self.options.artifact_check_all
+                || path.starts_with("buildSrc")
+                || path.starts_with("build-logic")
+                || path.iter().count() <= depth)
     }
 
-    /// Given a line of code, return it with the first, if any, mapping found in the list of
-    /// patterns to check, whether a replacement occurred, and if the line contained a star import.
+    /// Given a line of code, return the potentially new line with androidx package names and what
+    /// kind of match, if any, was found on the line.
     ///
     /// * `line` - The source code line
-    /// * `patterns` - An array of patterns mapped to replacements
-    fn match_line_with_patterns<'a, 'b>(
-        &self,
-        line: &'a str,
-        patterns: &'b [Mapping],
-    ) -> (Cow<'a, str>, bool, bool) {
+    fn find_match<'a>(&self, line: &'a str) -> (Cow<'a, str>, LineMatch<'_>) {
+        if self.options.exclude_patterns.is_match(line) || self.categories_to_check(line).is_empty()
+        {
+            return (Cow::Borrowed(line), LineMatch::None);
+        }
         // Fast fail on star import that matches one of the migration minimum matchings
         if STAR_IMPORT_MATCH.is_match(line) {
-            return (Cow::Borrowed(line), false, true);
+            return (Cow::Borrowed(line), LineMatch::StarImport);
         }
 
-        for mapping in patterns.iter() {
-            // Finish fast, it's very unlikely that there will be more than one match on a line
-            if mapping.pattern.is_match(&line) {
-                return (
-                    mapping.pattern.replace(line, mapping.replacement.as_str()),
-                    true,
-                    false,
-                );
+        // Since the line has already passed the minimum match for the patterns, a line without any
+        // matching pattern is unrecognized
+        let mut matches = self.find_all_matches(line);
+        match matches.pop() {
+            Some((mapping, category, new_line)) => {
+                let mut applied: Vec<_> = matches
+                    .into_iter()
+                    .map(|(mapping, category, _)| (mapping, category))
+                    .collect();
+                applied.push((mapping, category));
+                (Cow::Owned(new_line), LineMatch::Replaced(applied))
             }
+            None => (Cow::Borrowed(line), LineMatch::Unrecognized),
         }
-        (Cow::Borrowed(line), false, false)
     }
 
-    /// Given a line of code finds any artifacts that need to be updated. The matching
-    /// ArtifactMapping will be returned if there are any.
+    /// Every set of mappings worth checking the given line against, along with their category.
+    ///
+    /// Some simple heuristics are done to make sure it's even worth checking the full set of
+    /// patterns: the line has to be at least as long as the shortest pattern and contain the
+    /// minimum match of the category.
+    ///
+    /// * `line` - The source code line
+    fn categories_to_check<'s>(&'s self, line: &str) -> Vec<(&'s [Mapping], Category)> {
+        let len = line.trim().len();
+        IntoIterator::into_iter([
+            (
+                &self.support,
+                Category::Support,
+                self.support_min_len,
+                &*SUPPORT_MIN_MATCH,
+            ),
+            (
+                &self.arch,
+                Category::Arch,
+                self.arch_min_len,
+                &*ARCH_MIN_MATCH,
+            ),
+            (
+                &self.databind,
+                Category::Databind,
+                self.databind_min_len,
+                &*DATABIND_MIN_MATCH,
+            ),
+            (
+                &self.kotlinx,
+                Category::Kotlinx,
+                self.kotlinx_min_len,
+                &*KOTLINX_MIN_MATCH,
+            ),
+        ])
+        .filter(|(_, _, min_len, min_match)| len >= *min_len && min_match.is_match(line))
+        .map(|(mappings, category, _, _)| (mappings.as_slice(), category))
+        .collect()
+    }
+
+    /// Given a line of code, apply every matching pattern to it one after the other, each on the
+    /// output of the previous one, so lines referencing several old classes are fully migrated.
+    /// Excluded lines and star imports have no matches.
     ///
     /// * `line` - The source code line
-    fn find_artifact_match(&self, line: &str) -> Option<&'static ArtifactMapping> {
-        if line.trim().len() >= *ARTIFACT_MIN_MATCH_LEN && ARTIFACT_MIN_MATCH.is_match(line) {
-            for mapping in ARTIFACT_MAPPINGS.iter() {
-                if mapping.pattern.find(line).is_some() {
-                    return Some(&mapping);
+    ///
+    /// Returns each applied mapping and its category along with the line as it was right after
+    /// applying it
+    pub fn find_all_matches(&self, line: &str) -> Vec<(&Mapping, Category, String)> {
+        let mut matches = Vec::new();
+        if self.options.exclude_patterns.is_match(line) || STAR_IMPORT_MATCH.is_match(line) {
+            return matches;
+        }
+
+        let mut current = String::from(line);
+        for (mappings, category) in self.categories_to_check(line) {
+            for mapping in mappings {
+                if mapping.pattern.is_match(&current) {
+                    current = mapping
+                        .pattern
+                        .replace_all(&current, mapping.replacement.as_str())
+                        .into_owned();
+                    matches.push((mapping, category, current.clone()));
                 }
             }
         }
+        matches
+    }
+
+    /// Find every mapping that matches the given line, in the order they're checked, to debug why a
+    /// line is or isn't migrated the way it's expected to be.
+    ///
+    /// * `line` - The source code line
+    ///
+    /// Returns an explanation for each matching mapping
+    pub fn explain(&self, line: &str) -> Vec<Explanation> {
+        let applied = match self.find_match(line).1 {
+            LineMatch::Replaced(applied) => applied,
+            _ => Vec::new(),
+        };
+        let applied = &applied;
+        [
+            (Category::Support, &self.support),
+            (Category::Arch, &self.arch),
+            (Category::Databind, &self.databind),
+            (Category::Kotlinx, &self.kotlinx),
+        ]
+        .iter()
+        .flat_map(|(category, mappings)| {
+            mappings.iter().filter_map(move |mapping| {
+                mapping.pattern.find(line).map(|found| Explanation {
+                    pattern: String::from(mapping.pattern.as_str()),
+                    replacement: mapping.replacement.clone(),
+                    category: *category,
+                    range: found.range(),
+                    applied: applied
+                        .iter()
+                        .any(|(applied, _)| std::ptr::eq(*applied, mapping)),
+                })
+            })
+        })
+        .collect()
+    }
+
+    /// Given a line of code finds every artifact that needs to be updated. The matching
+    /// ArtifactMappings will be returned along with the declared versions if there are any. A
+    /// joined line can hold several declarations, e.g. a wrapped `listOf(` block, so every
+    /// artifact is returned with the line it starts on.
+    ///
+    /// * `line` - The source code line, joined up with any lines it continues onto
+    fn find_artifact_matches(&self, line: &JoinedLine) -> Vec<ArtifactMatch> {
+        let text = line.text.as_str();
+        if text.trim().len() < self.artifacts_min_len || !ARTIFACT_MIN_MATCH.is_match(text) {
+            return Vec::new();
+        }
+
+        // Earlier mappings take priority when several match at the same spot, same as for a
+        // single artifact, and matches overlapping one already taken are skipped
+        let mut found: Vec<_> = self
+            .artifacts
+            .iter()
+            .flat_map(|mapping| {
+                mapping
+                    .pattern
+                    .find_iter(text)
+                    .map(move |found| (found.start(), found.end(), mapping))
+            })
+            .collect();
+        found.sort_by_key(|(start, _, _)| *start);
+        let mut artifacts = Vec::new();
+        let mut taken_until = 0;
+        for (start, end, mapping) in found {
+            if start < taken_until {
+                continue;
+            }
+            taken_until = end;
+            artifacts.push(ArtifactMatch {
+                mapping: mapping.clone(),
+                line_number: line.line_number_at(start),
+                version: find_artifact_version(&text[end..]),
+            });
+        }
+
+        artifacts
+    }
+}
+
+/// One or more build file lines joined up by [`LineJoiner`]
+#[derive(Debug, PartialEq)]
+struct JoinedLine {
+    /// The joined lines, without any continuation backslashes
+    text: String,
+    /// The byte offset in `text` each line starts at, along with its 1-based line number
+    line_starts: Vec<(usize, usize)>,
+}
+
+impl JoinedLine {
+    /// The 1-based line number of the line that the given part of the text came from.
+    ///
+    /// * `offset` - The byte offset into the joined text
+    fn line_number_at(&self, offset: usize) -> usize {
+        self.line_starts
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= offset)
+            .map_or(0, |(_, line_number)| *line_number)
+    }
+}
+
+/// Joins up build file lines that continue onto the next line, so an artifact declaration that was
+/// wrapped is still found. Lines continue when they end with a backslash, or while a parenthesis
+/// is left open like in Groovy, up to [`MAX_CONTINUED_LINES`] lines.
+#[derive(Debug, Default)]
+struct LineJoiner {
+    /// The byte offset each line joined so far starts at, along with its 1-based line number
+    line_starts: Vec<(usize, usize)>,
+    /// The lines joined so far, without any continuation backslashes
+    joined: String,
+    /// The number of parentheses still open
+    open_parens: usize,
+}
+
+impl LineJoiner {
+    /// Add the next line.
+    ///
+    /// * `line` - The line to add
+    /// * `line_number` - The 1-based line number of the line
+    ///
+    /// Returns the full joined line once it doesn't continue any further
+    fn push(&mut self, line: &str, line_number: usize) -> Option<JoinedLine> {
+        self.line_starts.push((self.joined.len(), line_number));
+        if self.line_starts.len() == 1 {
+            self.joined.push_str(line);
+        } else {
+            self.joined.push_str(line.trim_start());
+        }
+        for c in line.chars() {
+            match c {
+                '(' => self.open_parens += 1,
+                ')' => self.open_parens = self.open_parens.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        let backslash = self.joined.trim_end().ends_with('\\');
+        if backslash {
+            let len = self.joined.trim_end().len() - 1;
+            self.joined.truncate(len);
+        }
+        if (backslash || self.open_parens > 0) && self.line_starts.len() < MAX_CONTINUED_LINES {
+            None
+        } else {
+            self.finish()
+        }
+    }
+
+    /// Finish up the lines joined so far, e.g. at the end of the file.
+    ///
+    /// Returns the joined line, if there were any lines
+    fn finish(&mut self) -> Option<JoinedLine> {
+        if self.line_starts.is_empty() {
+            return None;
+        }
+        self.open_parens = 0;
+        Some(JoinedLine {
+            text: std::mem::take(&mut self.joined),
+            line_starts: std::mem::take(&mut self.line_starts),
+        })
+    }
+}
 
+/// The length of the shortest of the given patterns, or zero if there aren't any.
+///
+/// * `patterns` - The patterns to check
+fn min_pattern_len<'a>(patterns: impl Iterator<Item = &'a Regex>) -> usize {
+    patterns
+        .map(|pattern| pattern.as_str().len())
+        .min()
+        .unwrap_or_default()
+}
+
+/// Given the rest of a line directly after an artifact's group and name, return the version it is
+/// declared with, if any. Interpolated versions such as `$version` or `${versions.support}` are
+/// returned verbatim.
+///
+/// * `rest` - The remainder of the line following the artifact
+fn find_artifact_version(rest: &str) -> Option<String> {
+    let version: String = rest
+        .strip_prefix(':')?
+        .chars()
+        .take_while(|&c| c != '"' && c != '\'' && c != ')' && c != '@' && !c.is_whitespace())
+        .collect();
+
+    if version.is_empty() {
         None
+    } else {
+        Some(version)
+    }
+}
+
+/// Where a migrated file is written to in the output directory. Only the normal components of the
+/// path are kept so absolute paths or `..` can't escape the output directory and overwrite the
+/// original.
+///
+/// * `output_dir` - The directory migrated files are written to
+/// * `path` - The path of the original file
+fn output_path(output_dir: &Path, path: &Path) -> PathBuf {
+    let relative: PathBuf = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    output_dir.join(relative)
+}
+
+/// Whether the given file contents use Windows style line endings, judged by the first line.
+///
+/// * `source` - The contents, or at least the start, of the file
+fn uses_crlf(source: &[u8]) -> bool {
+    match source.iter().position(|&b| b == b'\n') {
+        Some(i) => i > 0 && source[i - 1] == b'\r',
+        None => false,
     }
 }
 
@@ -223,12 +1390,23 @@ mod tests {
 
         assert!(match_info.matches_found == 0);
         assert!(match_info.artifacts_found.len() == 1);
+        assert_eq!(match_info.artifacts_found[0].line_number, 2);
         assert!(match_info
             .artifacts_found
             .first()
             .unwrap()
-            .replacement
+            .mapping
+            .replacement_with_version()
             .contains("androidx.core:core:"));
+        assert_eq!(
+            match_info
+                .artifacts_found
+                .first()
+                .unwrap()
+                .version
+                .as_deref(),
+            Some("28.0.0")
+        );
     }
 
     #[test]
@@ -257,7 +1435,7 @@ mod tests {
         let contents = fs::read_to_string(path).unwrap();
 
         assert!(match_info.matches_found == 2);
-        assert!(match_info.matched_star_imports.len() == 0);
+        assert!(match_info.matched_star_imports.is_empty());
         assert_eq!(contents, expected);
     }
 
@@ -287,10 +1465,10 @@ mod tests {
         let contents = fs::read_to_string(path).unwrap();
 
         assert!(match_info.matches_found == 3);
-        assert!(match_info.matched_star_imports.len() == 1);
+        assert!(match_info.matched_star_imports.is_empty());
         assert_eq!(
-            match_info.matched_star_imports.first().unwrap(),
-            "            -dontwarn android.support.design.**"
+            match_info.unrecognized_patterns,
+            vec!["            -dontwarn android.support.design.**"]
         );
         assert_eq!(contents, expected);
     }
@@ -324,8 +1502,18 @@ mod tests {
         let contents = fs::read_to_string(path).unwrap();
 
         assert!(match_info.matches_found == 3);
-        assert!(match_info.matched_star_imports.len() == 0);
+        assert!(match_info.matched_star_imports.is_empty());
         assert_eq!(contents, expected);
+        assert_eq!(match_info.replaced_lines.len(), 3);
+        assert_eq!(
+            match_info.replaced_lines[1],
+            ReplacedLine {
+                line_number: 4,
+                old: String::from("                @android.support.annotation.NonNull"),
+                new: String::from("                @androidx.annotation.NonNull"),
+                description: None,
+            }
+        );
     }
 
     #[test]
@@ -370,57 +1558,806 @@ mod tests {
 
         assert!(match_info.matches_found == 3);
         assert!(match_info.matched_star_imports.len() == 1);
+        assert_eq!(match_info.star_import_line_numbers, vec![4]);
+        assert_eq!(match_info.category_counts[&Category::Support], 1);
+        assert_eq!(match_info.category_counts[&Category::Arch], 1);
+        assert_eq!(match_info.category_counts[&Category::Databind], 1);
         assert_eq!(contents, expected);
     }
 
-    // find_match/match_line_with_patterns tests
-
     #[test]
-    fn xml_matching_is_replaced() {
-        let matcher = create_matcher();
-        let line = "</android.support.constraint.ConstraintLayout>";
-        let new_line = "</androidx.constraintlayout.widget.ConstraintLayout>";
-        let (replacement, changed, found_star) = matcher.find_match(line);
+    fn file_larger_than_max_size_is_skipped() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        let source = "import android.support.annotation.NonNull;\n";
+        file.write_all(source.as_bytes()).unwrap();
+        file.flush().unwrap();
 
-        assert_eq!(replacement, new_line);
-        assert!(changed);
-        assert!(!found_star)
-    }
+        // Run it
+        let path = file.path().to_path_buf();
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            max_file_size: Some(10),
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(path.clone())
+            .unwrap();
 
-    #[test]
-    fn annotation_matching_is_replaced() {
-        let matcher = create_matcher();
-        let line = "        @set:android.support.annotation.VisibleForTesting";
-        let new_line = "        @set:androidx.annotation.VisibleForTesting";
-        let (replacement, changed, found_star) = matcher.find_match(line);
+        let contents = fs::read_to_string(path).unwrap();
 
-        assert_eq!(replacement, new_line);
-        assert!(changed);
-        assert!(!found_star)
+        assert!(match_info.skipped_too_large);
+        assert!(match_info.matches_found == 0);
+        assert_eq!(match_info.file_size, source.len() as u64);
+        assert_eq!(contents, source);
     }
 
     #[test]
-    fn kdoc_comment_matching_is_replaced() {
-        let matcher = create_matcher();
-        let line = "* uses [android.arch.lifecycle.ViewModel] to do stuff.";
-        let new_line = "* uses [androidx.lifecycle.ViewModel] to do stuff.";
-        let (replacement, changed, found_star) = matcher.find_match(line);
-
-        assert_eq!(replacement, new_line);
-        assert!(changed);
-        assert!(!found_star);
-    }
+    fn only_artifacts_finds_artifacts_without_migrating() {
+        // Set up the test file
+        let mut file = Builder::new()
+            .prefix("build")
+            .suffix(".gradle")
+            .tempfile_in("")
+            .unwrap();
+        let source = "import android.support.annotation.NonNull;
+            dependencies {
+                implemenation 'com.android.support:support-compat:28.0.0'
+            }\n";
+        file.write_all(source.as_bytes()).unwrap();
+        file.flush().unwrap();
 
-    #[test]
+        // Run it
+        let path_buf = file.path().to_path_buf();
+        let path = path_buf.file_name().unwrap();
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            only_artifacts: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(PathBuf::from(path))
+            .unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+
+        assert_eq!(match_info.matches_found, 0);
+        assert_eq!(match_info.artifacts_found.len(), 1);
+        assert_eq!(contents, source);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_is_replaced_without_modifying_target() {
+        // Set up the test file
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("Target.java");
+        let link = dir.path().join("Link.java");
+        let source = "import android.support.annotation.NonNull;\n";
+        fs::write(&target, source).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        // Run it
+        let match_info = create_matcher().search_and_replace(link.clone()).unwrap();
+
+        assert_eq!(match_info.matches_found, 1);
+        assert!(!fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            fs::read_to_string(&link).unwrap(),
+            "import androidx.annotation.NonNull;\n"
+        );
+        assert_eq!(fs::read_to_string(&target).unwrap(), source);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_is_skipped_when_not_following_symlinks() {
+        // Set up the test file
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("Target.java");
+        let link = dir.path().join("Link.java");
+        let source = "import android.support.annotation.NonNull;\n";
+        fs::write(&target, source).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            no_follow_symlinks: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(link.clone())
+            .unwrap();
+
+        assert!(match_info.skipped_symlink);
+        assert_eq!(match_info.matches_found, 0);
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), source);
+    }
+
+    #[test]
+    fn mapping_hits_are_counted_when_profiling() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            "import android.support.annotation.NonNull;
+import android.support.annotation.NonNull;
+import android.support.annotation.Nullable;\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            dry_run: true,
+            profile: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(path)
+            .unwrap();
+
+        assert_eq!(match_info.mapping_hits.len(), 2);
+        assert_eq!(
+            match_info.mapping_hits["android.support.annotation.NonNull"],
+            2
+        );
+        assert_eq!(
+            match_info.mapping_hits["android.support.annotation.Nullable"],
+            1
+        );
+    }
+
+    #[test]
+    fn modification_time_is_preserved() {
+        // Set up the test file with a modification time well in the past
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all("import android.support.annotation.NonNull;\n".as_bytes())
+            .unwrap();
+        file.flush().unwrap();
+        let path = file.path().to_path_buf();
+        let mtime = FileTime::from_unix_time(1_500_000_000, 0);
+        filetime::set_file_mtime(&path, mtime).unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            preserve_timestamps: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(path.clone())
+            .unwrap();
+
+        let metadata = fs::metadata(path).unwrap();
+
+        assert_eq!(match_info.matches_found, 1);
+        assert_eq!(FileTime::from_last_modification_time(&metadata), mtime);
+    }
+
+    #[test]
+    fn aidl_imports_are_replaced() {
+        // Set up the test file
+        let mut file = Builder::new().suffix(".aidl").tempfile().unwrap();
+        file.write_all(
+            "package com.example.aidl;
+
+import android.support.v4.os.ResultReceiver;
+import android.support.v4.media.MediaMetadataCompat;
+
+interface IExampleService {
+    void send(in ResultReceiver receiver, in MediaMetadataCompat metadata);
+}
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let match_info = create_matcher().search_and_replace(path.clone()).unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+
+        // Classes that kept their support library package still count as a match
+        assert_eq!(match_info.matches_found, 2);
+        assert!(match_info.unrecognized_patterns.is_empty());
+        assert_eq!(
+            contents,
+            "package com.example.aidl;
+
+import androidx.core.os.ResultReceiver;
+import android.support.v4.media.MediaMetadataCompat;
+
+interface IExampleService {
+    void send(in ResultReceiver receiver, in MediaMetadataCompat metadata);
+}
+"
+        );
+    }
+
+    #[test]
+    fn aidl_support_package_declaration_is_unrecognized() {
+        // Set up the test file
+        let mut file = Builder::new().suffix(".aidl").tempfile().unwrap();
+        file.write_all(
+            "package android.support.v4.os;
+
+parcelable ResultReceiver;
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let match_info = create_matcher().search_and_replace(path).unwrap();
+
+        // A package on its own isn't a class, so there's no exact mapping to apply
+        assert_eq!(match_info.matches_found, 0);
+        assert_eq!(
+            match_info.unrecognized_patterns,
+            vec!["package android.support.v4.os;"]
+        );
+    }
+
+    #[test]
+    fn changes_over_max_replacements_are_not_written() {
+        // Set up the test files
+        let source = "import android.support.annotation.NonNull;\n";
+        let mut first = NamedTempFile::new().unwrap();
+        first.write_all(source.as_bytes()).unwrap();
+        first.flush().unwrap();
+        let mut second = NamedTempFile::new().unwrap();
+        second.write_all(source.as_bytes()).unwrap();
+        second.flush().unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            max_replacements: Some(1),
+            ..Default::default()
+        };
+        let matcher = Matcher::new(0, tx, options);
+        let first_result = matcher.search_and_replace(first.path().to_path_buf());
+        let second_result = matcher.search_and_replace(second.path().to_path_buf());
+
+        assert!(first_result.is_ok());
+        assert!(matches!(
+            second_result,
+            Err(MigrationError::TooManyReplacements(_))
+        ));
+        assert_eq!(
+            fs::read_to_string(first.path()).unwrap(),
+            "import androidx.annotation.NonNull;\n"
+        );
+        assert_eq!(fs::read_to_string(second.path()).unwrap(), source);
+    }
+
+    #[test]
+    fn changes_over_replacement_count_threshold_are_not_written() {
+        // Set up the test file
+        let source = "import android.support.annotation.NonNull;
+import android.support.annotation.Nullable;
+";
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(source.as_bytes()).unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            replacement_count_threshold: Some(1),
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(file.path().to_path_buf())
+            .unwrap();
+
+        assert!(match_info.skipped_high_count);
+        assert_eq!(match_info.matches_found, 2);
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), source);
+    }
+
+    #[test]
+    fn lines_over_regex_timeout_are_reported() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"import android.support.annotation.NonNull;\n}\n")
+            .unwrap();
+        file.flush().unwrap();
+
+        // Run it, every line takes longer than no time at all
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            regex_timeout: Some(Duration::ZERO),
+            dry_run: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(file.path().to_path_buf())
+            .unwrap();
+
+        assert!(!match_info.slow_lines.is_empty());
+        assert!(match_info
+            .slow_lines
+            .iter()
+            .all(|(line_number, _)| (1..=2).contains(line_number)));
+
+        // Nothing is timed without a timeout
+        let match_info = create_matcher()
+            .search_and_replace(file.path().to_path_buf())
+            .unwrap();
+        assert!(match_info.slow_lines.is_empty());
+    }
+
+    #[test]
+    fn lines_before_first_replacement_are_streamed() {
+        // Set up the test file with plenty of lines before anything has to change
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Example.java");
+        let mut source = String::from("package com.example;\n");
+        for i in 0..1000 {
+            source.push_str(&format!("// filler line {}\n", i));
+        }
+        source.push_str("import android.support.annotation.NonNull;\n}\n");
+        fs::write(&path, &source).unwrap();
+
+        // Run it
+        let match_info = create_matcher().search_and_replace(path.clone()).unwrap();
+
+        assert_eq!(match_info.matches_found, 1);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            source.replace("android.support.annotation", "androidx.annotation")
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn streamed_changes_are_discarded_over_replacement_count_threshold() {
+        // Set up the test file
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Example.java");
+        let source = "import android.support.annotation.NonNull;
+import android.support.annotation.Nullable;
+";
+        fs::write(&path, source).unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            replacement_count_threshold: Some(1),
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(path.clone())
+            .unwrap();
+
+        // The temp file the lines were streamed to is cleaned up
+        assert!(match_info.skipped_high_count);
+        assert_eq!(fs::read_to_string(&path).unwrap(), source);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn output_dir_gets_changes_instead_of_original() {
+        // Set up the test file
+        let source = "import android.support.annotation.NonNull;\n";
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(source.as_bytes()).unwrap();
+        file.flush().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            output_dir: Some(output_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let path = file.path().to_path_buf();
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(path.clone())
+            .unwrap();
+
+        let output = output_path(output_dir.path(), &path);
+        assert!(output.starts_with(output_dir.path()));
+        assert_eq!(match_info.matches_found, 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), source);
+        assert_eq!(
+            fs::read_to_string(output).unwrap(),
+            "import androidx.annotation.NonNull;\n"
+        );
+    }
+
+    #[test]
+    fn no_tempfile_writes_original_file() {
+        // Set up the test file, the migrated line is shorter so the file has to be truncated
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"import android.support.annotation.NonNull;\n")
+            .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            no_tempfile: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(file.path().to_path_buf())
+            .unwrap();
+
+        assert_eq!(match_info.matches_found, 1);
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            "import androidx.annotation.NonNull;\n"
+        );
+    }
+
+    #[test]
+    fn output_path_stays_in_output_dir() {
+        let output_dir = Path::new("out");
+
+        assert_eq!(
+            output_path(output_dir, Path::new("app/src/A.java")),
+            Path::new("out/app/src/A.java")
+        );
+        assert_eq!(
+            output_path(output_dir, Path::new("/tmp/../A.java")),
+            Path::new("out/tmp/A.java")
+        );
+    }
+
+    #[test]
+    fn aborted_matcher_skips_files() {
+        let (tx, rx) = unbounded();
+        let options = MatcherOptions::default();
+        options.aborted.store(true, Ordering::SeqCst);
+        Matcher::new(0, tx, options).process(PathBuf::from("does-not-exist.java"));
+
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn aborted_matcher_stops_running() {
+        let (tx, rx) = unbounded();
+        let (tx_paths, rx_paths) = unbounded();
+        tx_paths.send(PathBuf::from("A.java")).unwrap();
+        tx_paths.send(PathBuf::from("B.java")).unwrap();
+        let options = MatcherOptions::default();
+        options.aborted.store(true, Ordering::SeqCst);
+        Matcher::new(0, tx, options).run(rx_paths.clone());
+
+        assert!(rx.try_recv().is_err());
+        assert_eq!(rx_paths.len(), 1);
+    }
+
+    #[test]
+    fn potential_matches_are_counted() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            "package com.example;
+
+import android.support.annotation.NonNull;
+import android.support.v4.app.*;
+import android.support.NotARealClass;
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let match_info = create_matcher().search_and_replace(path).unwrap();
+
+        assert_eq!(match_info.unchanged_lines, 2);
+        assert_eq!(match_info.total_lines, 5);
+        assert_eq!(match_info.lines_with_potential_matches, 3);
+        assert_eq!(match_info.difficulty(), 60.0);
+    }
+
+    #[test]
+    fn every_class_on_a_line_is_migrated() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            "class Foo : android.support.v4.app.Fragment(), android.arch.lifecycle.LifecycleObserver\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let match_info = create_matcher().search_and_replace(path.clone()).unwrap();
+
+        assert_eq!(match_info.matches_found, 2);
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "class Foo : androidx.fragment.app.Fragment(), androidx.lifecycle.LifecycleObserver\n"
+        );
+    }
+
+    #[test]
+    fn file_is_migrated_without_mmap() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            "package com.example.java;\r\nimport android.support.annotation.NonNull;\r\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            no_mmap: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(path.clone())
+            .unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+
+        assert_eq!(match_info.matches_found, 1);
+        assert_eq!(
+            contents,
+            "package com.example.java;\r\nimport androidx.annotation.NonNull;\r\n"
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings_are_preserved() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            "package com.example.java;\r\nimport android.support.annotation.NonNull;\r\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let match_info = create_matcher().search_and_replace(path.clone()).unwrap();
+
+        let expected = "package com.example.java;\r\nimport androidx.annotation.NonNull;\r\n";
+        let contents = fs::read_to_string(path).unwrap();
+
+        assert!(match_info.matches_found == 1);
+        assert_eq!(contents, expected);
+    }
+
+    #[test]
+    fn dry_run_finds_matches_without_writing() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        let source = "import android.support.annotation.NonNull;\n";
+        file.write_all(source.as_bytes()).unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(path.clone())
+            .unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+
+        assert!(match_info.matches_found == 1);
+        assert_eq!(match_info.file_size, source.len() as u64);
+        assert_eq!(match_info.dry_run_result, Some(DryRunResult::WriteSkipped));
+        assert_eq!(contents, source);
+    }
+
+    #[test]
+    fn file_without_matches_is_not_written() {
+        // Set up the test file with a modification time well in the past
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all("import androidx.annotation.NonNull;\n".as_bytes())
+            .unwrap();
+        file.flush().unwrap();
+        let path = file.path().to_path_buf();
+        let mtime = FileTime::from_unix_time(1_500_000_000, 0);
+        filetime::set_file_mtime(&path, mtime).unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let match_info = Matcher::new(0, tx, MatcherOptions::default())
+            .search_and_replace(path.clone())
+            .unwrap();
+
+        let metadata = fs::metadata(path).unwrap();
+
+        assert_eq!(match_info.matches_found, 0);
+        assert_eq!(match_info.dry_run_result, None);
+        assert_eq!(FileTime::from_last_modification_time(&metadata), mtime);
+    }
+
+    #[test]
+    fn dry_run_without_matches_has_no_changes() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all("import androidx.annotation.NonNull;\n".as_bytes())
+            .unwrap();
+        file.flush().unwrap();
+
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(file.path().to_path_buf())
+            .unwrap();
+
+        assert_eq!(match_info.dry_run_result, Some(DryRunResult::NoChanges));
+    }
+
+    #[test]
+    fn unmapped_support_class_is_reported() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            "import android.support.annotation.NonNull;
+            import android.support.example.DoesNotExist;\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let match_info = create_matcher().search_and_replace(path).unwrap();
+
+        assert!(match_info.matches_found == 1);
+        assert_eq!(
+            match_info.unrecognized_patterns,
+            vec!["            import android.support.example.DoesNotExist;"]
+        );
+    }
+
+    #[test]
+    fn mapped_file_counts_towards_peak() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        let source = "import android.support.annotation.NonNull;\n";
+        file.write_all(source.as_bytes()).unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        create_matcher().search_and_replace(path).unwrap();
+
+        assert!(peak_mapped_bytes() >= source.len());
+    }
+
+    #[test]
+    fn non_utf8_file_is_a_decode_error() {
+        // Set up the test file, "café" in ISO-8859-1
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"// caf\xe9\n").unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let result = create_matcher().search_and_replace(path);
+
+        assert!(matches!(result, Err(MigrationError::Decode(_))));
+    }
+
+    #[test]
+    fn non_utf8_file_keeps_its_encoding() {
+        // Set up the test file, "café" in ISO-8859-1
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"// caf\xe9\nimport android.support.annotation.NonNull;\n")
+            .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let path = file.path().to_path_buf();
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            encoding: Some(encoding_rs::WINDOWS_1252),
+            ..Default::default()
+        };
+        let match_info = Matcher::new(0, tx, options)
+            .search_and_replace(path.clone())
+            .unwrap();
+
+        let contents = fs::read(path).unwrap();
+
+        assert_eq!(match_info.matches_found, 1);
+        assert_eq!(
+            contents,
+            b"// caf\xe9\nimport androidx.annotation.NonNull;\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn missing_file_is_an_open_error() {
+        let result = create_matcher().search_and_replace(PathBuf::from("does-not-exist.java"));
+
+        assert!(matches!(result, Err(MigrationError::Open(_, _))));
+    }
+
+    // find_match tests
+
+    #[test]
+    fn xml_matching_is_replaced() {
+        let matcher = create_matcher();
+        let line = "</android.support.constraint.ConstraintLayout>";
+        let new_line = "</androidx.constraintlayout.widget.ConstraintLayout>";
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, new_line);
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Support
+        ));
+    }
+
+    #[test]
+    fn annotation_matching_is_replaced() {
+        let matcher = create_matcher();
+        let line = "        @set:android.support.annotation.VisibleForTesting";
+        let new_line = "        @set:androidx.annotation.VisibleForTesting";
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, new_line);
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Support
+        ));
+    }
+
+    #[test]
+    fn kdoc_comment_matching_is_replaced() {
+        let matcher = create_matcher();
+        let line = "* uses [android.arch.lifecycle.ViewModel] to do stuff.";
+        let new_line = "* uses [androidx.lifecycle.ViewModel] to do stuff.";
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, new_line);
+        assert!(
+            matches!(line_match, LineMatch::Replaced(ref applied) if applied[0].1 == Category::Arch)
+        );
+    }
+
+    #[test]
     fn proguard_matching_line_is_replaced() {
         let matcher = create_matcher();
         let line = "-keep public class * extends android.support.v4.app.Fragment";
         let new_line = "-keep public class * extends androidx.fragment.app.Fragment";
-        let (replacement, changed, found_star) = matcher.find_match(line);
+        let (replacement, line_match) = matcher.find_match(line);
 
         assert_eq!(replacement, new_line);
-        assert!(changed);
-        assert!(!found_star);
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Support
+        ));
     }
 
     #[test]
@@ -428,11 +2365,57 @@ mod tests {
         let matcher = create_matcher();
         let line = "import android.support.animation.Force;";
         let new_line = "import androidx.dynamicanimation.animation.Force;";
-        let (replacement, changed, found_star) = matcher.find_match(line);
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, new_line);
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Support
+        ));
+    }
+
+    #[test]
+    fn espresso_static_import_is_replaced() {
+        let matcher = create_matcher();
+        let line = "import static android.support.test.espresso.Espresso.onView;";
+        let new_line = "import static androidx.test.espresso.Espresso.onView;";
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, new_line);
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Support
+        ));
+    }
+
+    #[test]
+    fn test_runner_is_replaced() {
+        let matcher = create_matcher();
+        let line =
+            r#"        testInstrumentationRunner "android.support.test.runner.AndroidJUnitRunner""#;
+        let new_line =
+            r#"        testInstrumentationRunner "androidx.test.runner.AndroidJUnitRunner""#;
+        let (replacement, line_match) = matcher.find_match(line);
 
         assert_eq!(replacement, new_line);
-        assert!(changed);
-        assert!(!found_star)
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Support
+        ));
+    }
+
+    #[test]
+    fn test_rule_is_replaced() {
+        let matcher = create_matcher();
+        let line = "import android.support.test.rule.ActivityTestRule;";
+        let new_line = "import androidx.test.rule.ActivityTestRule;";
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, new_line);
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Support
+        ));
     }
 
     #[test]
@@ -440,11 +2423,12 @@ mod tests {
         let matcher = create_matcher();
         let line = "val page: android.arch.paging.PageResult? = null";
         let new_line = "val page: androidx.paging.PageResult? = null";
-        let (replacement, changed, found_star) = matcher.find_match(line);
+        let (replacement, line_match) = matcher.find_match(line);
 
         assert_eq!(replacement, new_line);
-        assert!(changed);
-        assert!(!found_star)
+        assert!(
+            matches!(line_match, LineMatch::Replaced(ref applied) if applied[0].1 == Category::Arch)
+        );
     }
 
     #[test]
@@ -452,33 +2436,388 @@ mod tests {
         let matcher = create_matcher();
         let line = "public void (android.databinding.Observable obs) {";
         let new_line = "public void (androidx.databinding.Observable obs) {";
-        let (replacement, changed, found_star) = matcher.find_match(line);
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, new_line);
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Databind
+        ));
+    }
+
+    #[test]
+    fn parcelize_import_is_replaced() {
+        let matcher = create_matcher();
+        let line = "import kotlinx.android.parcel.Parcelize";
+        let new_line = "import kotlinx.parcelize.Parcelize";
+        let (replacement, line_match) = matcher.find_match(line);
 
         assert_eq!(replacement, new_line);
-        assert!(changed);
-        assert!(!found_star)
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Kotlinx
+        ));
+    }
+
+    #[test]
+    fn synthetic_view_import_is_unrecognized() {
+        let matcher = create_matcher();
+        let line = "import kotlinx.android.synthetic.main.activity_main.toolbar";
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, line);
+        assert!(matches!(line_match, LineMatch::Unrecognized))
+    }
+
+    #[test]
+    fn synthetic_view_star_import_is_star_import() {
+        let matcher = create_matcher();
+        let line = "import kotlinx.android.synthetic.main.activity_main.*";
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, line);
+        assert!(matches!(line_match, LineMatch::StarImport))
     }
 
     #[test]
     fn too_short_of_line_is_ignored() {
         let matcher = create_matcher();
         let line = "}";
-        let (replacement, changed, found_star) = matcher.find_match(line);
+        let (replacement, line_match) = matcher.find_match(line);
 
         assert_eq!(replacement, line);
-        assert!(!changed);
-        assert!(!found_star)
+        assert!(matches!(line_match, LineMatch::None));
     }
 
     #[test]
     fn star_import_gives_back_same_line() {
         let matcher = create_matcher();
         let line = "import android.support.annotation.*";
-        let (replacement, changed, found_star) = matcher.find_match(line);
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, line);
+        assert!(matches!(line_match, LineMatch::StarImport));
+    }
+
+    #[test]
+    fn uncertain_and_deprecated_replacements_are_recorded() {
+        // Set up the test file
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            "import android.support.annotation.NonNull;
+import android.support.example.Split;\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        // Run it
+        let (tx, _) = unbounded();
+        let mut support = SUPPORT_MAPPINGS.to_vec();
+        support.push(Mapping {
+            pattern: Regex::new("android.support.example.Split").unwrap(),
+            replacement: String::from("androidx.example.Split"),
+            description: None,
+            confidence: Some(0.5),
+            deprecated: Some(String::from("Moved again to androidx.example.split")),
+        });
+        let matcher = Matcher::new_with_mappings(
+            0,
+            tx,
+            MatcherOptions::default(),
+            support,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        );
+        let match_info = matcher
+            .search_and_replace(file.path().to_path_buf())
+            .unwrap();
+
+        assert_eq!(match_info.matches_found, 2);
+        assert_eq!(
+            match_info.low_confidence_replacements,
+            vec![(2, String::from("androidx.example.Split"))]
+        );
+        assert_eq!(
+            match_info.deprecated_replacements,
+            vec![(2, String::from("Moved again to androidx.example.split"))]
+        );
+    }
+
+    #[test]
+    fn match_info_displays_everything_found() {
+        let mut match_info = MatchInfo::empty(0, PathBuf::from("app/src/Main.java"), 0);
+        match_info.matches_found = 2;
+        match_info.matched_star_imports = vec![String::from("import android.support.v4.app.*;")];
+        match_info.unrecognized_patterns = vec![String::from("android.support.example.Missing")];
+        match_info.low_confidence_replacements = vec![(3, String::from("androidx.example.Split"))];
+
+        assert_eq!(
+            match_info.to_string(),
+            "app/src/Main.java: 2 replacement(s)\n\
+             Found 1 star import(s) that must be updated in app/src/Main.java:\n  \
+             * import android.support.v4.app.*;\n\
+             Found 1 unrecognized support library reference(s) in app/src/Main.java:\n  \
+             * android.support.example.Missing\n\
+             Found 1 uncertain replacement(s) that should be checked in app/src/Main.java:\n  \
+             * line 3: androidx.example.Split?"
+        );
+    }
+
+    #[test]
+    fn match_info_warnings_are_in_print_order() {
+        let mut match_info = MatchInfo::empty(0, PathBuf::from("Main.kt"), 0);
+        match_info.slow_lines = vec![(7, Duration::from_millis(12))];
+        match_info.unrecognized_patterns = vec![String::from("android.support.example.Missing")];
+
+        let warnings = match_info.warnings();
+        assert_eq!(
+            warnings.iter().map(|w| w.kind).collect::<Vec<_>>(),
+            vec![WarningKind::Unrecognized, WarningKind::SlowLines]
+        );
+        assert_eq!(
+            warnings[1].to_string(),
+            "Found 1 line(s) over the regex timeout in Main.kt:\n  * line 7: 12ms"
+        );
+    }
+
+    #[test]
+    fn default_match_info_has_nothing_found() {
+        let match_info = MatchInfo::default();
+
+        assert_eq!(match_info.matches_found, 0);
+        assert!(match_info.artifacts_found.is_empty());
+        assert!(match_info.matched_star_imports.is_empty());
+        assert_eq!(match_info.path, PathBuf::new());
+    }
+
+    #[test]
+    fn empty_match_info_displays_only_replacements() {
+        let match_info = MatchInfo::empty(0, PathBuf::from("Main.kt"), 0);
+        assert_eq!(match_info.to_string(), "Main.kt: 0 replacement(s)");
+    }
+
+    #[test]
+    fn custom_mappings_are_used() {
+        let (tx, _) = unbounded();
+        let support = vec![Mapping {
+            pattern: Regex::new("android.support.example.Custom").unwrap(),
+            replacement: String::from("androidx.example.Custom"),
+            description: None,
+            confidence: None,
+            deprecated: None,
+        }];
+        let matcher = Matcher::new_with_mappings(
+            0,
+            tx,
+            MatcherOptions::default(),
+            support,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        );
+
+        let (replacement, line_match) =
+            matcher.find_match("import android.support.example.Custom;");
+        assert_eq!(replacement, "import androidx.example.Custom;");
+        assert!(matches!(
+            line_match,
+            LineMatch::Replaced(ref applied) if applied[0].1 == Category::Support
+        ));
+
+        let line = "import android.support.annotation.NonNull;";
+        let (replacement, line_match) = matcher.find_match(line);
+        assert_eq!(replacement, line);
+        assert!(matches!(line_match, LineMatch::Unrecognized));
+    }
+
+    // uses_crlf tests
+
+    #[test]
+    fn crlf_source_is_detected() {
+        assert!(uses_crlf(
+            b"import android.support.annotation.NonNull;\r\n}\r\n"
+        ))
+    }
+
+    #[test]
+    fn lf_source_is_not_crlf() {
+        assert!(!uses_crlf(
+            b"import android.support.annotation.NonNull;\n}\n"
+        ))
+    }
+
+    #[test]
+    fn single_line_source_is_not_crlf() {
+        assert!(!uses_crlf(b"}"))
+    }
+
+    #[test]
+    fn unmapped_support_class_is_unrecognized() {
+        let matcher = create_matcher();
+        let line = "import android.support.example.DoesNotExist;";
+        let (replacement, line_match) = matcher.find_match(line);
+
+        assert_eq!(replacement, line);
+        assert!(matches!(line_match, LineMatch::Unrecognized))
+    }
+
+    // may_declare_artifacts tests
+
+    #[test]
+    fn module_build_file_may_declare_artifacts() {
+        let matcher = create_matcher();
+
+        assert!(matcher.may_declare_artifacts(Path::new("app/build.gradle")));
+        assert!(!matcher.may_declare_artifacts(Path::new("app/src/main/AndroidManifest.xml")));
+        assert!(!matcher.may_declare_artifacts(Path::new("libs/core/build.gradle")));
+    }
+
+    #[test]
+    fn build_logic_may_declare_artifacts() {
+        let matcher = create_matcher();
+        let path = Path::new("build-logic/conventions/src/main/kotlin/Android.kt");
+
+        assert!(matcher.may_declare_artifacts(path));
+    }
+
+    #[test]
+    fn artifact_depth_allows_deeper_build_files() {
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            artifact_depth: Some(3),
+            ..Default::default()
+        };
+        let matcher = Matcher::new(0, tx, options);
+
+        assert!(matcher.may_declare_artifacts(Path::new("libs/core/build.gradle")));
+        assert!(!matcher.may_declare_artifacts(Path::new("libs/core/src/build.gradle")));
+    }
+
+    #[test]
+    fn artifact_depth_is_counted_from_subdir() {
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            subdir: Some(PathBuf::from("libs")),
+            ..Default::default()
+        };
+        let matcher = Matcher::new(0, tx, options);
+
+        assert!(matcher.may_declare_artifacts(Path::new("libs/core/build.gradle")));
+        assert!(matcher.may_declare_artifacts(Path::new("libs/buildSrc/src/Deps.kt")));
+        assert!(!matcher.may_declare_artifacts(Path::new("libs/core/src/build.gradle")));
+    }
 
+    #[test]
+    fn artifact_check_can_be_disabled_or_unrestricted() {
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            no_artifact_check: true,
+            ..Default::default()
+        };
+        let matcher = Matcher::new(0, tx.clone(), options);
+        assert!(!matcher.may_declare_artifacts(Path::new("build.gradle")));
+
+        let options = MatcherOptions {
+            artifact_check_all: true,
+            ..Default::default()
+        };
+        let matcher = Matcher::new(0, tx, options);
+        assert!(matcher.may_declare_artifacts(Path::new("libs/core/src/build.gradle")));
+        assert!(!matcher.may_declare_artifacts(Path::new("libs/core/src/main.xml")));
+    }
+
+    // find_all_matches tests
+
+    #[test]
+    fn all_matches_on_a_line_are_applied() {
+        let matcher = create_matcher();
+        let line = "class Foo : android.support.v4.app.Fragment(), android.arch.lifecycle.LifecycleObserver";
+        let matches = matcher.find_all_matches(line);
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(
+            matches.last().unwrap().2,
+            "class Foo : androidx.fragment.app.Fragment(), androidx.lifecycle.LifecycleObserver"
+        );
+    }
+
+    #[test]
+    fn min_pattern_length_overrides_shortest_pattern() {
+        let (tx, _) = unbounded();
+        let line = "import android.support.v4.app.Fragment;";
+        let options = MatcherOptions {
+            min_pattern_length: Some(line.len() + 1),
+            ..Default::default()
+        };
+        let matcher = Matcher::new(0, tx, options);
+
+        let (replacement, line_match) = matcher.find_match(line);
         assert_eq!(replacement, line);
-        assert!(!changed);
-        assert!(found_star)
+        assert!(matches!(line_match, LineMatch::None));
+    }
+
+    #[test]
+    fn excluded_line_is_left_untouched() {
+        let (tx, _) = unbounded();
+        let options = MatcherOptions {
+            exclude_patterns: RegexSet::new(["^\\s*//", "@Keep"]).unwrap(),
+            ..Default::default()
+        };
+        let matcher = Matcher::new(0, tx, options);
+
+        for line in [
+            "    // Used to be android.support.v4.app.Fragment",
+            "@Keep import android.support.v4.app.Fragment;",
+        ] {
+            let (replacement, line_match) = matcher.find_match(line);
+            assert_eq!(replacement, line);
+            assert!(matches!(line_match, LineMatch::None));
+        }
+
+        let (replacement, _) = matcher.find_match("import android.support.v4.app.Fragment;");
+        assert_eq!(replacement, "import androidx.fragment.app.Fragment;");
+    }
+
+    #[test]
+    fn star_import_has_no_matches() {
+        let matcher = create_matcher();
+        let line = "import android.support.v4.app.*;";
+
+        assert!(matcher.find_all_matches(line).is_empty());
+    }
+
+    #[test]
+    fn explain_lists_every_matching_mapping() {
+        let matcher = create_matcher();
+        let line = "import android.support.v4.app.FragmentActivity;";
+        let explanations = matcher.explain(line);
+
+        assert!(explanations.len() > 1);
+        let applied: Vec<&Explanation> = explanations.iter().filter(|e| e.applied).collect();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(
+            applied[0].replacement,
+            "androidx.fragment.app.FragmentActivity"
+        );
+        assert_eq!(applied[0].category, Category::Support);
+        assert_eq!(
+            &line[applied[0].range.clone()],
+            "android.support.v4.app.FragmentActivity"
+        );
+    }
+
+    #[test]
+    fn explain_shows_matches_that_are_not_applied() {
+        let matcher = create_matcher();
+        // Too short to pass the quick checks, so nothing is applied even though a mapping matches
+        let explanations = matcher.explain("android.support.v4.app.Fragment");
+
+        assert!(!explanations.is_empty());
+        assert!(explanations.iter().all(|e| !e.applied));
     }
 
     // find_artifact_match tests
@@ -488,7 +2827,25 @@ mod tests {
         let matcher = create_matcher();
         let line = r#"    implemenation "com.android.support:car:28.0.0""#;
 
-        assert!(matcher.find_artifact_match(line).is_some())
+        assert!(find_artifact_match(&matcher, line).is_some())
+    }
+
+    #[test]
+    fn artifact_displays_full_replacement_with_version() {
+        let matcher = create_matcher();
+        let artifact = find_artifact_match(
+            &matcher,
+            r#"    implementation "com.android.support:appcompat-v7:28.0.0""#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            artifact.to_string(),
+            format!(
+                "{:<60}=> androidx.appcompat:appcompat:1.1.0 (currently 28.0.0)",
+                "com.android.support:appcompat-v7"
+            )
+        );
     }
 
     #[test]
@@ -496,7 +2853,108 @@ mod tests {
         let matcher = create_matcher();
         let line = "    implemenation 'com.android.support:car:$version'";
 
-        assert!(matcher.find_artifact_match(line).is_some())
+        assert!(find_artifact_match(&matcher, line).is_some())
+    }
+
+    #[test]
+    fn artifact_line_with_interpolated_version_returns_version() {
+        let matcher = create_matcher();
+        let line = r#"    implemenation "com.android.support:appcompat-v7:${versions.support}""#;
+        let artifact = find_artifact_match(&matcher, line).unwrap();
+
+        assert_eq!(artifact.version.as_deref(), Some("${versions.support}"))
+    }
+
+    #[test]
+    fn artifact_line_with_variable_version_returns_version() {
+        let matcher = create_matcher();
+        let line = "    implemenation 'com.android.support:car:$version'";
+        let artifact = find_artifact_match(&matcher, line).unwrap();
+
+        assert_eq!(artifact.version.as_deref(), Some("$version"))
+    }
+
+    #[test]
+    fn artifact_line_without_version_returns_no_version() {
+        let matcher = create_matcher();
+        let line = r#"val CAR = "com.android.support:car""#;
+        let artifact = find_artifact_match(&matcher, line).unwrap();
+
+        assert_eq!(artifact.version, None)
+    }
+
+    #[test]
+    fn backslash_continued_artifact_is_found() {
+        let mut joiner = LineJoiner::default();
+
+        assert_eq!(
+            joiner.push("    implementation 'com.android.support:\\", 3),
+            None
+        );
+        let line = joiner.push("        appcompat-v7:28.0.0'", 4).unwrap();
+        let artifacts = create_matcher().find_artifact_matches(&line);
+
+        assert_eq!(
+            line.text,
+            "    implementation 'com.android.support:appcompat-v7:28.0.0'"
+        );
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].line_number, 3);
+        assert_eq!(artifacts[0].version.as_deref(), Some("28.0.0"));
+    }
+
+    #[test]
+    fn lines_in_open_parentheses_are_joined() {
+        let mut joiner = LineJoiner::default();
+
+        assert_eq!(joiner.push("implementation(", 1), None);
+        assert_eq!(joiner.push("    'com.android.support:car:28.0.0'", 2), None);
+        assert_eq!(
+            joiner.push(")", 3),
+            Some(JoinedLine {
+                text: String::from("implementation('com.android.support:car:28.0.0')"),
+                line_starts: vec![(0, 1), (15, 2), (47, 3)],
+            })
+        );
+        assert_eq!(
+            joiner.push("}", 4),
+            Some(JoinedLine {
+                text: String::from("}"),
+                line_starts: vec![(0, 4)],
+            })
+        );
+        assert_eq!(joiner.finish(), None);
+    }
+
+    #[test]
+    fn every_artifact_in_open_parentheses_is_found() {
+        let mut joiner = LineJoiner::default();
+
+        assert_eq!(joiner.push("def support = listOf(", 1), None);
+        assert_eq!(
+            joiner.push("    'com.android.support:appcompat-v7:28.0.0',", 2),
+            None
+        );
+        assert_eq!(
+            joiner.push("    'com.android.support:design:$version'", 3),
+            None
+        );
+        let line = joiner.push(")", 4).unwrap();
+        let artifacts = create_matcher().find_artifact_matches(&line);
+
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(
+            artifacts[0].mapping.replacement,
+            "androidx.appcompat:appcompat"
+        );
+        assert_eq!(artifacts[0].line_number, 2);
+        assert_eq!(artifacts[0].version.as_deref(), Some("28.0.0"));
+        assert_eq!(
+            artifacts[1].mapping.replacement,
+            "com.google.android.material:material"
+        );
+        assert_eq!(artifacts[1].line_number, 3);
+        assert_eq!(artifacts[1].version.as_deref(), Some("$version"));
     }
 
     #[test]
@@ -504,12 +2962,20 @@ mod tests {
         let matcher = create_matcher();
         let line = r#"val LIB = "com.example.android.support:lib:$VERSION""#;
 
-        assert!(matcher.find_artifact_match(line).is_none())
+        assert!(find_artifact_match(&matcher, line).is_none())
+    }
+
+    fn find_artifact_match(matcher: &Matcher, line: &str) -> Option<ArtifactMatch> {
+        let line = JoinedLine {
+            text: String::from(line),
+            line_starts: vec![(0, 1)],
+        };
+        matcher.find_artifact_matches(&line).into_iter().next()
     }
 
     fn create_matcher() -> Matcher {
         let (tx, _) = unbounded();
 
-        Matcher { id: 0, tx }
+        Matcher::new(0, tx, MatcherOptions::default())
     }
 }
@@ -0,0 +1,176 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// Formats the results of a run can be printed to stdout in
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Progress and a summary for people to read
+    Text,
+    /// A CSV row per file with anything to migrate, e.g. for tracking the migration in a
+    /// spreadsheet
+    Csv,
+    /// A single JSON object with every file with anything to migrate once finished
+    Json,
+}
+
+/// Formats the old artifacts found in a run can be reported in
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtifactReportFormat {
+    /// A warning per build file as it's migrated
+    Text,
+    /// A single JSON object mapping each old artifact to its replacement once finished
+    Json,
+    /// TOML keys mapping each old artifact to its replacement once finished, e.g. to paste into a
+    /// version catalog
+    Toml,
+}
+
+/// Format the old artifacts found in a run along with their replacements.
+///
+/// * `format` - The format of the report
+/// * `artifacts` - Each old artifact mapped to its replacement
+///
+/// Returns the report, or None with the text format since artifacts are printed per file instead
+pub fn format_artifact_report(
+    format: ArtifactReportFormat,
+    artifacts: &BTreeMap<String, String>,
+) -> Option<String> {
+    match format {
+        ArtifactReportFormat::Text => None,
+        ArtifactReportFormat::Json => serde_json::to_string_pretty(artifacts)
+            .ok()
+            .map(|json| json + "\n"),
+        ArtifactReportFormat::Toml => toml::to_string(artifacts).ok(),
+    }
+}
+
+/// What was found in a single file, as a row of the CSV output or an entry of the JSON output
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileSummary {
+    /// The path of the file
+    pub path: String,
+    /// How many lines were migrated
+    pub replacements: usize,
+    /// How many old artifacts must be updated
+    pub artifacts_count: usize,
+    /// How many star imports must be migrated by hand
+    pub star_imports_count: usize,
+}
+
+impl FileSummary {
+    /// Whether there's anything worth listing for the file.
+    pub fn is_empty(&self) -> bool {
+        self.replacements == 0 && self.artifacts_count == 0 && self.star_imports_count == 0
+    }
+}
+
+/// Everything found in a run, printed with the JSON output
+#[derive(Debug, Serialize)]
+pub struct JsonReport {
+    /// A random ID to tell runs apart when their reports are stored together
+    pub run_id: String,
+    /// When the run started as an ISO-8601 timestamp in UTC
+    pub started_at: String,
+    /// The total number of lines migrated
+    pub replacements: usize,
+    /// Every file with anything to migrate
+    pub files: Vec<FileSummary>,
+}
+
+impl JsonReport {
+    /// Create the report for a run with a new random ID.
+    ///
+    /// * `started_at` - When the run started
+    /// * `files` - Every file with anything to migrate
+    pub fn new(started_at: SystemTime, files: Vec<FileSummary>) -> Self {
+        JsonReport {
+            run_id: Uuid::new_v4().to_string(),
+            started_at: OffsetDateTime::from(started_at)
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            replacements: files.iter().map(|file| file.replacements).sum(),
+            files,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows_have_a_header() {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .serialize(FileSummary {
+                path: String::from("app/src/A.java"),
+                replacements: 3,
+                artifacts_count: 0,
+                star_imports_count: 1,
+            })
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            "path,replacements,artifacts_count,star_imports_count\napp/src/A.java,3,0,1\n"
+        );
+    }
+
+    #[test]
+    fn artifact_report_maps_old_artifacts_to_replacements() {
+        let artifacts = BTreeMap::from([
+            (
+                String::from("com.android.support:appcompat-v7"),
+                String::from("androidx.appcompat:appcompat:1.0.0"),
+            ),
+            (
+                String::from("android.arch.core:common"),
+                String::from("androidx.arch.core:core-common:2.1.0"),
+            ),
+        ]);
+
+        assert_eq!(
+            format_artifact_report(ArtifactReportFormat::Toml, &artifacts).unwrap(),
+            "\"android.arch.core:common\" = \"androidx.arch.core:core-common:2.1.0\"\n\
+             \"com.android.support:appcompat-v7\" = \"androidx.appcompat:appcompat:1.0.0\"\n"
+        );
+        assert_eq!(
+            format_artifact_report(ArtifactReportFormat::Json, &artifacts).unwrap(),
+            "{\n  \"android.arch.core:common\": \"androidx.arch.core:core-common:2.1.0\",\n  \
+             \"com.android.support:appcompat-v7\": \"androidx.appcompat:appcompat:1.0.0\"\n}\n"
+        );
+        assert_eq!(
+            format_artifact_report(ArtifactReportFormat::Text, &artifacts),
+            None
+        );
+    }
+
+    #[test]
+    fn json_report_identifies_run() {
+        let report = JsonReport::new(
+            SystemTime::UNIX_EPOCH,
+            vec![FileSummary {
+                path: String::from("app/src/A.java"),
+                replacements: 3,
+                artifacts_count: 0,
+                star_imports_count: 1,
+            }],
+        );
+
+        assert_eq!(report.started_at, "1970-01-01T00:00:00Z");
+        assert_eq!(report.replacements, 3);
+        assert!(Uuid::parse_str(&report.run_id).is_ok());
+        assert_ne!(
+            report.run_id,
+            JsonReport::new(SystemTime::UNIX_EPOCH, Vec::new()).run_id
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// File recording what was migrated on the last run with --since-last-run
+pub const STATE_FILE: &str = ".rusty_jetpack_state";
+
+/// What was migrated on the last run, so files that haven't changed since can be skipped.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct State {
+    /// The git HEAD commit at the end of the last run, if it was in a git repo
+    pub head: Option<String>,
+    /// The hash of the mappings and options used on the last run
+    pub mappings: Option<String>,
+    /// The SHA-256 hash of each file that had nothing left to migrate
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl State {
+    /// Load the state from the given file. A missing or unreadable file is the same as never
+    /// having run before.
+    ///
+    /// * `path` - The path of the state file
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!(
+                    "Ignoring invalid state in {}: {}",
+                    path.to_string_lossy(),
+                    e
+                );
+                State::default()
+            }),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => State::default(),
+            Err(e) => {
+                eprintln!("Ignoring state in {}: {}", path.to_string_lossy(), e);
+                State::default()
+            }
+        }
+    }
+
+    /// Forget every recorded file if the mappings or options changed since the last run, since
+    /// files that were fully migrated before might not be anymore.
+    ///
+    /// * `mappings` - The hash of the mappings and options in use
+    pub fn for_mappings(mut self, mappings: String) -> Self {
+        if self.mappings.as_ref() != Some(&mappings) {
+            self.files.clear();
+            self.mappings = Some(mappings);
+        }
+        self
+    }
+
+    /// Write the state out to the given file.
+    ///
+    /// * `path` - The path of the state file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(path, contents + "\n")
+    }
+
+    /// Whether the file at the given path still has the same contents it had on the last run.
+    ///
+    /// * `path` - The path of the file relative to the root of the project
+    pub fn is_unchanged(&self, path: &Path) -> bool {
+        self.files
+            .get(path)
+            .is_some_and(|hash| hash_file(path).is_ok_and(|current| &current == hash))
+    }
+
+    /// Record the current contents of the given files and the current git HEAD. Files that can't
+    /// be read anymore are forgotten so they're migrated again next time.
+    ///
+    /// * `paths` - The files that have nothing left to migrate
+    pub fn record<'a>(&mut self, paths: impl Iterator<Item = &'a PathBuf>) {
+        for path in paths {
+            match hash_file(path) {
+                Ok(hash) => self.files.insert(path.clone(), hash),
+                Err(_) => self.files.remove(path),
+            };
+        }
+        self.head = git_head();
+    }
+}
+
+/// The SHA-256 hash of a file's contents as a hex string.
+///
+/// * `path` - The path of the file
+fn hash_file(path: &Path) -> io::Result<String> {
+    Ok(format!("{:x}", Sha256::digest(fs::read(path)?)))
+}
+
+/// The commit git's HEAD currently points to, if in a git repo.
+fn git_head() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("A.java");
+        fs::write(&source, "import androidx.annotation.NonNull;\n").unwrap();
+        let path = dir.path().join(STATE_FILE);
+
+        let mut state = State::default();
+        state.record([source].iter());
+        state.save(&path).unwrap();
+
+        assert_eq!(State::load(&path), state);
+    }
+
+    #[test]
+    fn changed_mappings_forget_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("A.java");
+        fs::write(&source, "import androidx.annotation.NonNull;\n").unwrap();
+        let mut state = State::default().for_mappings(String::from("old"));
+        state.record([source.clone()].iter());
+
+        let state = state.for_mappings(String::from("old"));
+        assert!(state.is_unchanged(&source));
+        let state = state.for_mappings(String::from("new"));
+        assert!(!state.is_unchanged(&source));
+    }
+
+    #[test]
+    fn missing_state_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(State::load(&dir.path().join(STATE_FILE)), State::default());
+    }
+
+    #[test]
+    fn changed_file_is_not_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("A.java");
+        fs::write(&source, "import androidx.annotation.NonNull;\n").unwrap();
+        let mut state = State::default();
+        state.record([source.clone()].iter());
+
+        assert!(state.is_unchanged(&source));
+        fs::write(&source, "import android.support.annotation.NonNull;\n").unwrap();
+        assert!(!state.is_unchanged(&source));
+    }
+}
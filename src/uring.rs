@@ -0,0 +1,170 @@
+use io_uring::{opcode, types, IoUring, Probe};
+
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+
+/// Max number of files to read with a single submission
+pub const BATCH_SIZE: usize = 32;
+
+/// Reads whole files in batches through io_uring to save on system calls compared to reading or
+/// mapping each file one at a time.
+pub struct BatchReader {
+    ring: IoUring,
+    max_file_size: Option<u64>,
+    /// Set once waiting on the ring fails, after which it isn't used again
+    failed: bool,
+}
+
+impl BatchReader {
+    /// Create a BatchReader, failing if the kernel doesn't support io_uring or its read operation.
+    ///
+    /// * `max_file_size` - Files larger than this number of bytes aren't read
+    pub fn new(max_file_size: Option<u64>) -> io::Result<Self> {
+        let ring = IoUring::new(BATCH_SIZE as u32)?;
+
+        // Reading into a plain buffer is only supported since Linux 5.6 which also added probing
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe)?;
+        if !probe.is_supported(opcode::Read::CODE) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "io_uring reads aren't supported by this kernel",
+            ));
+        }
+
+        Ok(BatchReader {
+            ring,
+            max_file_size,
+            failed: false,
+        })
+    }
+
+    /// Read the full contents of each of the given files with a single submission.
+    ///
+    /// * `paths` - The files to read, at most [`BATCH_SIZE`] of them
+    ///
+    /// Returns the contents of each file in the same order as the paths, or None for any file
+    /// that couldn't be read in full so it can be read the usual way instead
+    pub fn read_all(&mut self, paths: &[PathBuf]) -> Vec<Option<Vec<u8>>> {
+        if self.failed {
+            return vec![None; paths.len()];
+        }
+        let mut files: Vec<Option<(File, Vec<u8>)>> = paths
+            .iter()
+            .map(|path| {
+                let file = File::open(path).ok()?;
+                let len = file.metadata().ok()?.len();
+                if self.max_file_size.is_some_and(|max| len > max) || len > u32::MAX as u64 {
+                    return None;
+                }
+                Some((file, vec![0; len as usize]))
+            })
+            .collect();
+
+        let mut submitted = 0;
+        for (i, entry) in files.iter_mut().enumerate() {
+            if let Some((file, buf)) = entry {
+                let read = opcode::Read::new(
+                    types::Fd(file.as_raw_fd()),
+                    buf.as_mut_ptr(),
+                    buf.len() as u32,
+                )
+                .build()
+                .user_data(i as u64);
+                // Safety: the file and buffer outlive the read since every submitted read is
+                // waited on below before either of them can be dropped, or they're leaked if
+                // waiting fails.
+                if unsafe { self.ring.submission().push(&read) }.is_err() {
+                    *entry = None;
+                    continue;
+                }
+                submitted += 1;
+            }
+        }
+
+        let mut contents = vec![None; paths.len()];
+        let mut completed = 0;
+        while completed < submitted {
+            match self.ring.submit_and_wait(submitted - completed) {
+                Ok(_) => {}
+                // Any completions are reaped below to make room before waiting again
+                Err(ref e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::Interrupted
+                            | io::ErrorKind::WouldBlock
+                            | io::ErrorKind::ResourceBusy
+                    ) => {}
+                Err(_) => {
+                    // Reads that haven't completed may still be in flight and write into their
+                    // buffers at any point, so those are leaked instead of freed. The ring isn't
+                    // used again since it could still hold reads that were never submitted.
+                    self.failed = true;
+                    for entry in files.iter_mut() {
+                        mem::forget(entry.take());
+                    }
+                    break;
+                }
+            }
+            for completion in self.ring.completion() {
+                completed += 1;
+                let i = completion.user_data() as usize;
+                if let Some((_, buf)) = files[i].take() {
+                    // Short reads are left for the usual path instead of resubmitting them
+                    if completion.result() >= 0 && completion.result() as usize == buf.len() {
+                        contents[i] = Some(buf);
+                    }
+                }
+            }
+        }
+
+        contents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn files_are_read_in_full() {
+        let mut reader = match BatchReader::new(None) {
+            Ok(reader) => reader,
+            // Not every kernel running the tests supports io_uring
+            Err(_) => return,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("First.java");
+        let second = dir.path().join("Second.java");
+        fs::write(&first, "package com.example;\n").unwrap();
+        fs::write(&second, "").unwrap();
+
+        let contents = reader.read_all(&[first, second, dir.path().join("Missing.java")]);
+
+        assert_eq!(
+            contents,
+            vec![
+                Some(b"package com.example;\n".to_vec()),
+                Some(Vec::new()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn files_larger_than_max_size_are_not_read() {
+        let mut reader = match BatchReader::new(Some(4)) {
+            Ok(reader) => reader,
+            Err(_) => return,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Large.java");
+        fs::write(&path, "package com.example;\n").unwrap();
+
+        assert_eq!(reader.read_all(&[path]), vec![None]);
+    }
+}
@@ -0,0 +1,2 @@
+-keep class android.support.v4.app.Fragment { *; }
+-keep class com.example.app.** { *; }
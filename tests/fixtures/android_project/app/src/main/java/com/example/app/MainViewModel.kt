@@ -0,0 +1,5 @@
+package com.example.app
+
+import android.arch.lifecycle.ViewModel
+
+class MainViewModel : ViewModel()
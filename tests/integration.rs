@@ -0,0 +1,544 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// The fake Android project every test migrates a copy of
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/android_project"
+);
+
+/// Copy a directory and everything in it.
+///
+/// * `from` - The directory to copy
+/// * `to` - Where to copy it to
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+/// Copy the fixture project into a temporary directory so it can be migrated.
+fn fixture_project() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    copy_dir(Path::new(FIXTURE), dir.path());
+    dir
+}
+
+/// Run rusty_jetpack in the given project.
+///
+/// * `project` - The root of the project
+/// * `args` - The arguments to run with
+fn run(project: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rusty_jetpack"))
+        .current_dir(project)
+        .args(args)
+        // Make sure a git repo the temp directory happens to be in isn't picked up
+        .env("GIT_CEILING_DIRECTORIES", project.parent().unwrap())
+        .output()
+        .unwrap()
+}
+
+/// Read a file of the project.
+///
+/// * `project` - The root of the project
+/// * `path` - The path of the file relative to the root of the project
+fn read(project: &Path, path: &str) -> String {
+    fs::read_to_string(project.join(path)).unwrap()
+}
+
+#[test]
+fn fixture_project_is_migrated() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--diagnostic-format", "gcc"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", stderr);
+
+    let activity = read(
+        project.path(),
+        "app/src/main/java/com/example/app/MainActivity.java",
+    );
+    assert!(activity.contains("import androidx.annotation.Nullable;"));
+    assert!(activity.contains("import androidx.appcompat.app.AppCompatActivity;"));
+    assert!(activity.contains("import android.support.v4.app.*;"));
+    assert!(read(
+        project.path(),
+        "app/src/main/java/com/example/app/MainViewModel.kt"
+    )
+    .contains("import androidx.lifecycle.ViewModel"));
+    assert!(
+        read(project.path(), "app/src/main/res/layout/activity_main.xml")
+            .contains("<androidx.constraintlayout.widget.ConstraintLayout")
+    );
+    assert!(read(project.path(), "app/proguard-rules.pro")
+        .contains("-keep class androidx.fragment.app.Fragment { *; }"));
+    assert_eq!(
+        read(
+            project.path(),
+            "app/src/main/java/com/example/app/Greeter.java"
+        ),
+        fs::read_to_string(
+            Path::new(FIXTURE).join("app/src/main/java/com/example/app/Greeter.java")
+        )
+        .unwrap()
+    );
+
+    assert!(stderr.contains(
+        "app/src/main/java/com/example/app/MainActivity.java:6: warning: star import must be \
+         migrated by hand: import android.support.v4.app.*;"
+    ));
+    assert!(stderr.contains("app/build.gradle:4: warning: com.android.support:appcompat-v7"));
+    assert!(!stderr.contains("gson"));
+}
+
+#[test]
+fn check_reports_files_without_changing_them() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--check"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("app/src/main/java/com/example/app/MainActivity.java"));
+    assert!(!stdout.contains("Greeter.java"));
+    assert!(read(
+        project.path(),
+        "app/src/main/java/com/example/app/MainActivity.java"
+    )
+    .contains("import android.support.annotation.Nullable;"));
+}
+
+#[test]
+fn warnings_fail_the_run_when_treated_as_errors() {
+    let project = fixture_project();
+    assert_eq!(run(project.path(), &["--dry-run"]).status.code(), Some(0));
+
+    let output = run(project.path(), &["--dry-run", "--treat-warnings-as-errors"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn subdir_only_migrates_files_under_it() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--subdir", "./app/src", "--check"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("app/src/main/java/com/example/app/MainActivity.java"));
+    assert!(!stdout.contains("proguard-rules.pro"));
+    assert!(!stdout.contains("build.gradle"));
+}
+
+#[test]
+fn artifact_report_prints_only_artifacts() {
+    let project = fixture_project();
+    let output = run(
+        project.path(),
+        &["--dry-run", "--artifact-report-format", "toml"],
+    );
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\"com.android.support:appcompat-v7\" = \"androidx.appcompat:appcompat:1.1.0\"\n"
+    );
+}
+
+#[test]
+fn suggest_bom_version_prints_minimum_versions() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--dry-run", "--suggest-bom-version"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Minimum AndroidX versions:\n"));
+    assert!(stdout.contains("  androidx.appcompat 1.1.0\n"));
+}
+
+#[test]
+fn threads_per_core_uses_at_least_one_thread() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--check", "--threads-per-core", "0.01"]);
+
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Starting with 1 threads"));
+    assert!(!run(project.path(), &["--threads-per-core", "0"])
+        .status
+        .success());
+    assert!(!run(
+        project.path(),
+        &["--threads", "2", "--threads-per-core", "1"]
+    )
+    .status
+    .success());
+}
+
+#[test]
+fn show_patterns_lists_mappings_without_migrating() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--show-patterns"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(
+        stdout.contains("  android.support.v4.app.Fragment => androidx.fragment.app.Fragment\n")
+    );
+    assert!(stdout.contains("\nartifact ("));
+    assert!(read(
+        project.path(),
+        "app/src/main/java/com/example/app/MainActivity.java"
+    )
+    .contains("import android.support.annotation.Nullable;"));
+}
+
+#[test]
+fn benchmark_scans_without_writing() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--benchmark", "2"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Run 2/2: "));
+    assert!(stdout.contains("2 run(s) over 9 file(s)"));
+    assert!(stdout.contains("MB/s"));
+    assert!(read(
+        project.path(),
+        "app/src/main/java/com/example/app/MainActivity.java"
+    )
+    .contains("import android.support.annotation.Nullable;"));
+}
+
+#[test]
+fn config_env_reads_options_from_environment() {
+    let project = fixture_project();
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_jetpack"))
+        .current_dir(project.path())
+        .arg("--config-env")
+        .env("GIT_CEILING_DIRECTORIES", project.path().parent().unwrap())
+        .env("RUSTY_JETPACK_CHECK", "1")
+        .env("RUSTY_JETPACK_QUIET", "true")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!stdout.contains("Starting with"));
+    assert!(read(
+        project.path(),
+        "app/src/main/java/com/example/app/MainActivity.java"
+    )
+    .contains("import android.support.annotation.Nullable;"));
+}
+
+#[test]
+fn conflicting_env_options_are_an_error() {
+    let project = fixture_project();
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty_jetpack"))
+        .current_dir(project.path())
+        .args(["--config-env", "--dry-run"])
+        .env("GIT_CEILING_DIRECTORIES", project.path().parent().unwrap())
+        .env("RUSTY_JETPACK_QUIET", "1")
+        .env("RUSTY_JETPACK_VERBOSE", "1")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr.contains("cannot be used with"));
+}
+
+#[test]
+fn cli_option_overrides_conflicting_config_option() {
+    let project = fixture_project();
+    fs::write(
+        project.path().join(".rusty_jetpack.toml"),
+        "interactive = true\n",
+    )
+    .unwrap();
+    let output = run(project.path(), &["--check"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!stdout.contains("Apply changes?"));
+}
+
+#[test]
+fn conflicting_config_options_are_an_error() {
+    let project = fixture_project();
+    fs::write(
+        project.path().join(".rusty_jetpack.toml"),
+        "quiet = true\nverbose = true\n",
+    )
+    .unwrap();
+    let output = run(project.path(), &["--dry-run"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr.contains("cannot be used with"));
+}
+
+#[test]
+fn files_skipped_by_options_are_migrated_on_the_next_run() {
+    let project = fixture_project();
+    let view_model = "app/src/main/java/com/example/app/MainViewModel.kt";
+    let output = run(project.path(), &["--only-artifacts", "--since-last-run"]);
+
+    assert!(output.status.success());
+    assert!(!read(project.path(), view_model).contains("import androidx.lifecycle.ViewModel"));
+
+    let output = run(project.path(), &["--since-last-run"]);
+
+    assert!(output.status.success());
+    assert!(read(project.path(), view_model).contains("import androidx.lifecycle.ViewModel"));
+}
+
+#[test]
+fn sort_output_lists_files_in_order() {
+    let project = fixture_project();
+    let output = run(
+        project.path(),
+        &["--check", "--sort-output", "--threads", "4"],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let files: Vec<&str> = stdout.lines().filter(|l| l.starts_with("app/")).collect();
+
+    let mut sorted = files.clone();
+    sorted.sort_unstable();
+    assert_eq!(files.len(), 4);
+    assert_eq!(files, sorted);
+}
+
+#[test]
+fn only_files_prints_just_the_paths() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--only-files", "--sort-output"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert_eq!(
+        stdout,
+        "app/proguard-rules.pro\n\
+         app/src/main/java/com/example/app/MainActivity.java\n\
+         app/src/main/java/com/example/app/MainViewModel.kt\n\
+         app/src/main/res/layout/activity_main.xml\n"
+    );
+
+    let output = run(
+        project.path(),
+        &["--only-files", "--include-artifact-files", "--sort-output"],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("app/build.gradle\n"));
+}
+
+#[test]
+fn csv_output_has_a_row_per_file() {
+    let project = fixture_project();
+    let output = run(
+        project.path(),
+        &["--output-format", "csv", "--sort-output", "--dry-run"],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert_eq!(
+        stdout,
+        "path,replacements,artifacts_count,star_imports_count\n\
+         app/build.gradle,0,1,0\n\
+         app/proguard-rules.pro,1,0,0\n\
+         app/src/main/java/com/example/app/MainActivity.java,2,0,1\n\
+         app/src/main/java/com/example/app/MainViewModel.kt,1,0,0\n\
+         app/src/main/res/layout/activity_main.xml,2,0,0\n"
+    );
+}
+
+#[test]
+fn json_output_reports_every_file() {
+    let project = fixture_project();
+    let output = run(project.path(), &["--output-format", "json", "--dry-run"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(report["run_id"].is_string());
+    assert!(report["started_at"].is_string());
+    assert_eq!(report["replacements"], 6);
+    assert_eq!(report["files"].as_array().unwrap().len(), 5);
+}
+
+#[test]
+fn abort_on_error_exits_with_status_2() {
+    let project = fixture_project();
+    fs::write(
+        project.path().join(".rusty_jetpack"),
+        "app/src/main/java/com/example/app/Missing.java\n",
+    )
+    .unwrap();
+    let output = run(project.path(), &["--abort-on-error"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr.contains("Aborted after the first error"));
+}
+
+/// Run a git command in the given repo, panicking if it fails.
+///
+/// * `repo` - The root of the repo
+/// * `args` - The arguments to pass to git
+fn git(repo: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(repo)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Turn the fixture project into a git repo with only the `app/src/main/res` directory checked
+/// out.
+fn sparse_fixture_project() -> TempDir {
+    let project = fixture_project();
+    git(project.path(), &["init", "-q"]);
+    git(project.path(), &["add", "."]);
+    git(project.path(), &["commit", "-q", "-m", "Initial commit"]);
+    git(
+        project.path(),
+        &["sparse-checkout", "set", "app/src/main/res"],
+    );
+    project
+}
+
+#[test]
+fn sparse_checkout_only_migrates_checked_out_files() {
+    let project = sparse_fixture_project();
+    let output = run(project.path(), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Sparse checkout detected"));
+    assert!(!stderr.contains("Failed"));
+    assert!(
+        read(project.path(), "app/src/main/res/layout/activity_main.xml")
+            .contains("<androidx.constraintlayout.widget.ConstraintLayout")
+    );
+    assert!(!project
+        .path()
+        .join("app/src/main/java/com/example/app/MainActivity.java")
+        .exists());
+}
+
+#[test]
+fn staged_only_checks_staged_files() {
+    let project = fixture_project();
+    git(project.path(), &["init", "-q"]);
+    git(project.path(), &["add", "."]);
+    git(project.path(), &["commit", "-q", "-m", "Initial commit"]);
+    let view_model = project
+        .path()
+        .join("app/src/main/java/com/example/app/MainViewModel.kt");
+    let contents = fs::read_to_string(&view_model).unwrap();
+    fs::write(&view_model, contents + "// Staged\n").unwrap();
+    git(project.path(), &["add", "."]);
+
+    let output = run(project.path(), &["--staged", "--check"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("app/src/main/java/com/example/app/MainViewModel.kt"));
+    assert!(!stdout.contains("MainActivity.java"));
+}
+
+#[test]
+fn include_sparse_migrates_every_file_and_restores_checkout() {
+    let project = sparse_fixture_project();
+    let output = run(project.path(), &["--include-sparse"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", stderr);
+    assert!(read(
+        project.path(),
+        "app/src/main/java/com/example/app/MainActivity.java"
+    )
+    .contains("import androidx.annotation.Nullable;"));
+    assert_eq!(
+        git(project.path(), &["sparse-checkout", "list"]),
+        "app/src/main/res\n"
+    );
+}
+
+#[test]
+fn aborted_include_sparse_run_restores_checkout() {
+    let project = fixture_project();
+    // Not valid UTF-8, so migrating it fails and aborts the run
+    fs::write(
+        project
+            .path()
+            .join("app/src/main/java/com/example/app/Broken.java"),
+        b"import android.support.annotation.NonNull;\n\xff\n",
+    )
+    .unwrap();
+    git(project.path(), &["init", "-q"]);
+    git(project.path(), &["add", "."]);
+    git(project.path(), &["commit", "-q", "-m", "Initial commit"]);
+    git(
+        project.path(),
+        &["sparse-checkout", "set", "app/src/main/res"],
+    );
+
+    let output = run(
+        project.path(),
+        &["--include-sparse", "--abort-on-error", "--threads", "1"],
+    );
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        git(project.path(), &["sparse-checkout", "list"]),
+        "app/src/main/res\n"
+    );
+}
+
+#[test]
+fn git_commit_commits_migrated_files() {
+    let project = fixture_project();
+    git(project.path(), &["init", "-q"]);
+    git(project.path(), &["config", "user.name", "test"]);
+    git(
+        project.path(),
+        &["config", "user.email", "test@example.com"],
+    );
+    git(project.path(), &["add", "."]);
+    git(project.path(), &["commit", "-q", "-m", "Initial commit"]);
+
+    let output = run(project.path(), &["--git-commit"]);
+    assert!(output.status.success());
+    assert_eq!(
+        git(project.path(), &["log", "-1", "--format=%s"]).trim(),
+        "chore: migrate support library to AndroidX"
+    );
+    assert!(git(project.path(), &["status", "--porcelain"]).is_empty());
+
+    fs::write(
+        project
+            .path()
+            .join("app/src/main/java/com/example/app/Extra.java"),
+        "import android.support.annotation.NonNull;\n",
+    )
+    .unwrap();
+    git(project.path(), &["add", "."]);
+    git(project.path(), &["commit", "-q", "-m", "Add Extra"]);
+    let output = run(project.path(), &["--git-commit-amend"]);
+    assert!(output.status.success());
+    assert_eq!(
+        git(project.path(), &["log", "--format=%s"]).trim(),
+        "Add Extra\nchore: migrate support library to AndroidX\nInitial commit"
+    );
+    assert!(git(project.path(), &["status", "--porcelain"]).is_empty());
+}